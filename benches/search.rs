@@ -0,0 +1,103 @@
+//! Times `search` against going line by line with `str` methods, as it used to.
+//!
+//! Run with `cargo bench`; each case prints the best of several runs.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use minigrep::{search, Matcher};
+
+const RUNS: usize = 10;
+
+fn main() {
+    let corpus = corpus(32 * 1024 * 1024);
+    println!("corpus: {} MiB, best of {RUNS} runs", corpus.len() / (1024 * 1024));
+
+    // A longer query that never turns up leaves the most to skip.
+    for query in ["Sherlock Holmes", "zebra", "inquiry", "q", "Professor Moriarty"] {
+        let naive = time(|| line_by_line(&corpus, query));
+        let found = time(|| search(&Matcher::literal(query, false), &corpus).len());
+        report(&format!("search {query:?}"), naive, found);
+    }
+
+    // Lowercasing every line is what `-i` used to do.
+    for query in ["sherlock holmes", "zebra"] {
+        let naive = time(|| corpus.lines().filter(|line| line.to_lowercase().contains(query)).count());
+        let found = time(|| search(&Matcher::literal(query, true), &corpus).len());
+        report(&format!("search -i {query:?}"), naive, found);
+    }
+
+    // Several patterns at once, against trying each in turn on every line.
+    let patterns = ["zebra", "Moriarty", "quiet evening"].map(String::from);
+    let naive = time(|| corpus.lines().filter(|line| patterns.iter().any(|p| line.contains(p.as_str()))).count());
+    let found = time(|| search(&Matcher::literals(&patterns, false), &corpus).len());
+    report("search -e zebra -e ... (3)", naive, found);
+}
+
+/// What `search` used to do: look for `query` in every line, keeping each line's spans.
+fn line_by_line(contents: &str, query: &str) -> usize {
+    contents
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let spans: Vec<_> = line.match_indices(query).map(|(start, found)| start..start + found.len()).collect();
+            (!spans.is_empty()).then_some((i + 1, spans))
+        })
+        .count()
+}
+
+/// Runs `f` a few times and returns the quickest, along with what it returned.
+fn time(mut f: impl FnMut() -> usize) -> (Duration, usize) {
+    let mut best = Duration::MAX;
+    let mut result = 0;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        result = black_box(f());
+        best = best.min(start.elapsed());
+    }
+    (best, result)
+}
+
+fn report(name: &str, (before, expected): (Duration, usize), (after, got): (Duration, usize)) {
+    assert_eq!(expected, got, "{name} disagrees with the naive version");
+    println!(
+        "{name:<32} naive {:>9.2?}  minigrep {:>9.2?}  {:>5.1}x",
+        before,
+        after,
+        before.as_secs_f64() / after.as_secs_f64()
+    );
+}
+
+/// English-looking text of about `len` bytes, the same on every run.
+fn corpus(len: usize) -> String {
+    const WORDS: &[&str] = &[
+        "the", "of", "and", "to", "in", "a", "that", "was", "his", "he", "it", "with", "is", "for", "as", "had",
+        "you", "not", "be", "her", "on", "at", "by", "which", "have", "or", "from", "this", "him", "but", "all",
+        "she", "they", "were", "my", "are", "me", "one", "their", "so", "an", "said", "them", "we", "who",
+        "would", "been", "will", "no", "when", "there", "if", "more", "out", "up", "into", "do", "any", "your",
+        "what", "has", "man", "could", "other", "than", "our", "some", "very", "time", "upon", "about", "may",
+        "its", "only", "now", "like", "little", "then", "can", "should", "made", "did", "us", "such", "great",
+        "before", "must", "two", "these", "see", "know", "over", "much", "down", "after", "first", "Mr.", "good",
+        "men", "Holmes", "Sherlock", "Watson", "Baker", "Street", "evening", "quiet", "inquiry",
+    ];
+
+    let mut text = String::with_capacity(len + 128);
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut line_len = 0;
+    while text.len() < len {
+        // xorshift, so the corpus needs no dependencies and never changes.
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let word = WORDS[(state % WORDS.len() as u64) as usize];
+        text.push_str(word);
+        line_len += word.len() + 1;
+        if line_len > 70 {
+            text.push('\n');
+            line_len = 0;
+        } else {
+            text.push(' ');
+        }
+    }
+    text
+}
@@ -0,0 +1,405 @@
+//! Searching for many literal patterns in one pass over the text.
+//!
+//! This is the classic Aho-Corasick automaton: a trie of the patterns whose
+//! states also carry a failure link to the longest proper suffix that is
+//! still in the trie, so the text is read once no matter how many patterns
+//! there are.
+
+use std::collections::VecDeque;
+use std::ops::Range;
+
+use crate::casefold;
+use crate::substring;
+
+/// The most entries `dense` may have, about 16 MB of them.
+const DENSE_LIMIT: usize = 1 << 22;
+
+/// Marks an entry of `dense` that leads to a state where a pattern ends.
+const MATCH: u32 = 1 << 31;
+
+/// The most bytes `Prefilter` looks for, past which it would stop too often to help.
+const PREFILTER_LIMIT: usize = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct AhoCorasick {
+    /// The trie, searched through failure links when there is no `dense` table.
+    states: Vec<State>,
+    /// Every state's transition on every class of byte, one row per state, if
+    /// that isn't too big. Entries hold where the next state's row starts.
+    dense: Option<Vec<u32>>,
+    /// The column of `dense` for each byte. Bytes no pattern has share class 0,
+    /// and with `ignore_case` uppercase ASCII letters share their lowercase one's.
+    classes: Box<[u16; 256]>,
+    class_count: usize,
+    prefilter: Option<Prefilter>,
+    ignore_case: bool,
+    /// Length in bytes, case folded with `ignore_case`, of the longest pattern.
+    max_len: usize,
+    has_empty: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+struct State {
+    /// Sorted by byte.
+    next: Vec<(u8, usize)>,
+    fail: usize,
+    /// Lengths of every pattern ending here, including through failure links.
+    outputs: Vec<usize>,
+}
+
+/// The byte of each pattern least likely to show up in text, which the search
+/// can skip to while it is in the start state.
+#[derive(Debug, Clone, PartialEq)]
+struct Prefilter {
+    /// The rare bytes, each repeated across a word.
+    repeated: Vec<u64>,
+    /// How far into its pattern a rare byte can be.
+    back: usize,
+    /// Each pattern, case folded with `ignore_case`, with its rare byte and where that is in it.
+    patterns: Vec<(u8, usize, Box<[u8]>)>,
+}
+
+impl Prefilter {
+    /// Whether the rare byte at `at` in `bytes` could be part of an occurrence that
+    /// starts no earlier than `from`, which is as far back as `bytes` is ASCII.
+    fn might_match(&self, bytes: &[u8], from: usize, at: usize, ignore_case: bool) -> bool {
+        if !bytes[at].is_ascii() {
+            return true;
+        }
+        let byte = if ignore_case { bytes[at].to_ascii_lowercase() } else { bytes[at] };
+        self.patterns.iter().filter(|&&(rare, _, _)| rare == byte).any(|(_, offset, pattern)| {
+            let Some(start) = at.checked_sub(*offset).filter(|&start| start >= from) else { return false };
+            // Text that isn't ASCII could fold to anything, so it has to go through the automaton.
+            match bytes.get(start..start + pattern.len()) {
+                Some(window) if ignore_case => !window.is_ascii() || window.eq_ignore_ascii_case(pattern),
+                Some(window) => window == &pattern[..],
+                None => ignore_case && !bytes[start..].is_ascii(),
+            }
+        })
+    }
+}
+
+impl State {
+    fn goto(&self, byte: u8) -> Option<usize> {
+        self.next.binary_search_by_key(&byte, |&(b, _)| b).ok().map(|i| self.next[i].1)
+    }
+}
+
+impl AhoCorasick {
+    pub(crate) fn new(patterns: &[String], ignore_case: bool) -> AhoCorasick {
+        let mut states = vec![State::default()];
+        let mut max_len = 0;
+        let mut has_empty = false;
+        let mut rare_bytes = Vec::new();
+        let mut back = 0;
+        let mut rare_patterns = Vec::new();
+
+        for pattern in patterns {
+            let folded: String;
+            let pattern = if ignore_case {
+                folded = pattern.chars().flat_map(casefold::fold).collect();
+                &folded
+            } else {
+                pattern
+            };
+            let mut state = 0;
+            for &byte in pattern.as_bytes() {
+                state = match states[state].next.binary_search_by_key(&byte, |&(b, _)| b) {
+                    Ok(i) => states[state].next[i].1,
+                    Err(i) => {
+                        states.push(State::default());
+                        let next = states.len() - 1;
+                        states[state].next.insert(i, (byte, next));
+                        next
+                    }
+                };
+            }
+            states[state].outputs.push(pattern.len());
+            max_len = max_len.max(pattern.len());
+            has_empty |= pattern.is_empty();
+            let frequency = |byte: u8| substring::frequency(byte) + substring::frequency(byte.to_ascii_uppercase());
+            if let Some((i, &byte)) = pattern.as_bytes().iter().enumerate().min_by_key(|&(_, &byte)| frequency(byte)) {
+                rare_bytes.push(byte);
+                back = back.max(i);
+                rare_patterns.push((byte, i, Box::from(pattern.as_bytes())));
+            }
+        }
+        if ignore_case {
+            rare_bytes.extend(rare_bytes.clone().iter().filter(|byte| byte.is_ascii_lowercase()).map(u8::to_ascii_uppercase));
+        }
+        rare_bytes.sort();
+        rare_bytes.dedup();
+        let prefilter = (rare_bytes.len() <= PREFILTER_LIMIT).then(|| {
+            let repeated = rare_bytes.iter().map(|&byte| u64::from_ne_bytes([byte; 8])).collect();
+            Prefilter { repeated, back, patterns: rare_patterns }
+        });
+
+        // Breadth first, so a state's failure target is finished before the state itself.
+        let mut order = Vec::with_capacity(states.len());
+        let mut queue: VecDeque<usize> = states[0].next.iter().map(|&(_, s)| s).collect();
+        while let Some(state) = queue.pop_front() {
+            order.push(state);
+            for i in 0..states[state].next.len() {
+                let (byte, child) = states[state].next[i];
+                let mut fail = states[state].fail;
+                let target = loop {
+                    if let Some(next) = states[fail].goto(byte) {
+                        break next;
+                    }
+                    if fail == 0 {
+                        break 0;
+                    }
+                    fail = states[fail].fail;
+                };
+                states[child].fail = target;
+                let inherited = states[target].outputs.clone();
+                states[child].outputs.extend(inherited);
+                queue.push_back(child);
+            }
+        }
+
+        let mut classes = Box::new([0; 256]);
+        // A byte of each class.
+        let mut representatives = vec![0];
+        for state in &states {
+            for &(byte, _) in &state.next {
+                if classes[usize::from(byte)] == 0 {
+                    classes[usize::from(byte)] = representatives.len() as u16;
+                    representatives.push(byte);
+                }
+            }
+        }
+        let class_count = representatives.len();
+        if ignore_case {
+            for byte in b'A'..=b'Z' {
+                classes[usize::from(byte)] = classes[usize::from(byte.to_ascii_lowercase())];
+            }
+        }
+
+        let dense = (states.len() * class_count <= DENSE_LIMIT).then(|| {
+            let mut dense = vec![0; states.len() * class_count];
+            for state in std::iter::once(0).chain(order) {
+                for (class, &byte) in representatives.iter().enumerate().skip(1) {
+                    let fail = states[state].fail;
+                    dense[state * class_count + class] = match states[state].goto(byte) {
+                        Some(next) if states[next].outputs.iter().any(|&len| len > 0) => (next * class_count) as u32 | MATCH,
+                        Some(next) => (next * class_count) as u32,
+                        None if state == 0 => 0,
+                        // Rows of failure targets are always filled in first.
+                        None => dense[fail * class_count + class],
+                    };
+                }
+            }
+            dense
+        });
+
+        AhoCorasick { states, dense, classes, class_count, prefilter, ignore_case, max_len, has_empty }
+    }
+
+    /// Whether one of the patterns is empty, so that there is a match everywhere.
+    pub(crate) fn has_empty(&self) -> bool {
+        self.has_empty
+    }
+
+    /// Finds the leftmost, and then longest, occurrence of any pattern starting
+    /// at or after byte `start` for which `accept` returns true.
+    pub(crate) fn find_at(&self, text: &str, start: usize, accept: impl Fn(&Range<usize>) -> bool) -> Option<Range<usize>> {
+        let bytes = text.as_bytes();
+        let mut best = Some(start..start).filter(|empty| self.has_empty && accept(empty));
+        // How many bytes have gone through the automaton, and how many had when `best` started.
+        let mut fed = 0;
+        let mut best_fed = 0;
+        let mut state = 0;
+        let mut pos = start;
+        // Just past the rare byte the prefilter last stopped at, before which it would only stop there again.
+        let mut rare_end = pos;
+
+        while pos < bytes.len() {
+            // Every match still to come starts after `best` does.
+            if best.is_some() && fed + 1 > best_fed + self.max_len {
+                break;
+            }
+            if best.is_none() && !self.has_empty {
+                let prefilter = self.prefilter.as_ref().filter(|_| state == 0 && pos >= rare_end);
+                if let Some(prefilter) = prefilter {
+                    // Every occurrence from here on has a rare byte no more than `back` bytes into it.
+                    // With `ignore_case`, any byte that isn't ASCII might fold to part of a pattern.
+                    let might_match = |i| prefilter.might_match(bytes, pos, pos + i, self.ignore_case);
+                    let at = pos + substring::find_set(&prefilter.repeated, self.ignore_case, &bytes[pos..], might_match)?;
+                    rare_end = at + 1;
+                    let skipped = (at - pos).saturating_sub(prefilter.back);
+                    pos += skipped;
+                    fed += skipped;
+                }
+                if let Some(dense) = &self.dense {
+                    let resume = if self.prefilter.is_some() { rare_end.saturating_sub(pos) } else { usize::MAX };
+                    let skipped = self.skip(dense, &bytes[pos..], &mut state, resume);
+                    pos += skipped;
+                    fed += skipped;
+                    if pos == bytes.len() {
+                        break;
+                    }
+                }
+            }
+
+            let byte = bytes[pos];
+            if !self.ignore_case {
+                state = self.step(state, byte);
+                fed += 1;
+                pos += 1;
+            } else if byte.is_ascii() {
+                state = self.step(state, byte.to_ascii_lowercase());
+                fed += 1;
+                pos += 1;
+            } else {
+                let c = text[pos..].chars().next().unwrap();
+                let mut buf = [0; 4];
+                for folded in casefold::fold(c) {
+                    for &folded in folded.encode_utf8(&mut buf).as_bytes() {
+                        state = self.step(state, folded);
+                        fed += 1;
+                    }
+                }
+                pos += c.len_utf8();
+            }
+
+            for &len in &self.states[state].outputs {
+                if len == 0 {
+                    continue;
+                }
+                let Some(begin) = self.match_start(&text[start..pos], len) else { continue };
+                let candidate = start + begin..pos;
+                let better = match &best {
+                    None => true,
+                    Some(found) => {
+                        candidate.start < found.start || (candidate.start == found.start && candidate.end > found.end)
+                    }
+                };
+                if better && accept(&candidate) {
+                    best = Some(candidate);
+                    best_fed = fed - len;
+                }
+            }
+
+            // Empty patterns match at every position, but only the first counts as leftmost.
+            if best.is_none() && self.has_empty && text.is_char_boundary(pos) {
+                let empty = pos..pos;
+                if accept(&empty) {
+                    best = Some(empty);
+                    best_fed = fed;
+                }
+            }
+        }
+        best
+    }
+
+    /// Steps through `bytes` by the dense table for as long as that doesn't lead to
+    /// a pattern ending or, with `ignore_case`, to a byte that isn't ASCII, and
+    /// returns how many it took. It also stops on getting back to the start state
+    /// once `resume` bytes have been taken, so the prefilter can take over.
+    fn skip(&self, dense: &[u32], bytes: &[u8], state: &mut usize, resume: usize) -> usize {
+        let classes = &*self.classes;
+        let mut row = *state * self.class_count;
+        let mut taken = bytes.len();
+        for (i, &byte) in bytes.iter().enumerate() {
+            if self.ignore_case && !byte.is_ascii() {
+                taken = i;
+                break;
+            }
+            let next = dense[row + usize::from(classes[usize::from(byte)])];
+            if next & MATCH != 0 {
+                taken = i;
+                break;
+            }
+            row = next as usize;
+            if row == 0 && i + 1 >= resume {
+                taken = i + 1;
+                break;
+            }
+        }
+        *state = row / self.class_count;
+        taken
+    }
+
+    fn step(&self, mut state: usize, byte: u8) -> usize {
+        if let Some(dense) = &self.dense {
+            let next = dense[state * self.class_count + usize::from(self.classes[usize::from(byte)])];
+            return (next & !MATCH) as usize / self.class_count;
+        }
+        loop {
+            if let Some(next) = self.states[state].goto(byte) {
+                return next;
+            }
+            if state == 0 {
+                return 0;
+            }
+            state = self.states[state].fail;
+        }
+    }
+
+    /// Where in `text` a match that is `len` bytes long once folded, and ends at the
+    /// end of `text`, starts. A match has to take in the whole of every character
+    /// it touches, so there is none if that would be partway through one's folding.
+    fn match_start(&self, text: &str, len: usize) -> Option<usize> {
+        if !self.ignore_case {
+            return Some(text.len() - len);
+        }
+        let mut begin = text.len();
+        let mut folded_len = 0;
+        for c in text.chars().rev() {
+            if folded_len >= len {
+                break;
+            }
+            folded_len += if c.is_ascii() { 1 } else { casefold::fold(c).map(char::len_utf8).sum() };
+            begin -= c.len_utf8();
+        }
+        (folded_len == len).then_some(begin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(patterns: &[&str], ignore_case: bool, text: &str) -> Option<Range<usize>> {
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+        AhoCorasick::new(&patterns, ignore_case).find_at(text, 0, |_| true)
+    }
+
+    #[test]
+    fn leftmost_longest() {
+        assert_eq!(find(&["he", "she", "his", "hers"], false, "ushers"), Some(1..4));
+        assert_eq!(find(&["abc", "b", "bcdef"], false, "xabcdefg"), Some(1..4));
+        assert_eq!(find(&["cdef", "b"], false, "abcdef"), Some(1..2));
+        assert_eq!(find(&["ab", "abcd"], false, "xabcd"), Some(1..5));
+        assert_eq!(find(&["nope", "zzz"], false, "abcdef"), None);
+        assert_eq!(find(&["", "ab"], false, "ab"), Some(0..2));
+    }
+
+    #[test]
+    fn ignore_case_and_acceptance() {
+        assert_eq!(find(&["RUST", "go"], true, "I like Go and rust"), Some(7..9));
+        assert_eq!(find(&["strasse", "fox"], true, "Die Straße"), Some(4..11));
+        assert_eq!(find(&["s", "x"], true, "Maß"), None);
+
+        let patterns = vec!["foo-bar".to_string(), "foo".to_string()];
+        let ac = AhoCorasick::new(&patterns, false);
+        assert_eq!(ac.find_at("foo-barx", 0, |found| found.end != 7), Some(0..3));
+    }
+
+    #[test]
+    fn without_the_dense_table() {
+        let patterns: Vec<String> = ["he", "she", "his", "hers", "straße", "ünd"].map(String::from).to_vec();
+        for ignore_case in [false, true] {
+            let mut ac = AhoCorasick::new(&patterns, ignore_case);
+            assert!(ac.dense.is_some());
+            let texts = ["ushers", "USHERS and HIS", "Die STRASSE", "Hünd und HÜND", "nothing"];
+            let dense: Vec<_> = texts.iter().map(|text| ac.find_at(text, 0, |_| true)).collect();
+            ac.dense = None;
+            let sparse: Vec<_> = texts.iter().map(|text| ac.find_at(text, 0, |_| true)).collect();
+            assert_eq!(dense, sparse);
+        }
+        assert_eq!(find(&["ünd", "x"], true, "Hünd und HÜND"), Some(1..5));
+        assert_eq!(find(&["ünd", "x"], false, "Hünd"), Some(1..5));
+    }
+}
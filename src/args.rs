@@ -0,0 +1,502 @@
+//! Command-line parsing for `Config::build`.
+//!
+//! Options may be given anywhere before a `--`, short flags can be combined
+//! (`-ir`), and long options take their value either inline (`--opt=value`)
+//! or from the next argument.
+
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::encoding::Encoding;
+use crate::glob::Glob;
+use crate::regex::{self, Flags, Regex};
+use crate::{BinaryFiles, ColorChoice, Config, OutputMode, SortBy, CURRENT_DIR_PATH, STDIN_PATH};
+
+/// The text printed by `--help`.
+pub const USAGE: &str = "\
+Usage: minigrep [OPTIONS] QUERY [PATH]...
+   or: minigrep [OPTIONS] (-e PATTERN | -f FILE)... [PATH]...
+Search for QUERY in each PATH, or in standard input when no PATH or `-` is given
+(the current directory with -r).
+
+Options:
+  -i, --ignore-case         match without regard to case (the default when IGNORE_CASE is set)
+      --no-ignore-case      match case exactly, even when IGNORE_CASE is set
+  -S, --smart-case          ignore case unless a pattern has an uppercase letter
+  -e, --regexp=PATTERN      search for PATTERN, may be given more than once
+  -f, --file=FILE           search for each line of FILE
+  -E, --regex               treat QUERY as a regular expression
+  -w, --word-regexp         only match whole words
+  -x, --line-regexp         only match whole lines
+  -v, --invert-match        select lines that don't match
+  -r, --recursive           search directories recursively
+      --no-ignore           don't skip files that .gitignore, .ignore or .minigrepignore exclude
+      --include=GLOB        only search files whose name matches GLOB, may be repeated
+      --exclude=GLOB        skip files whose name matches GLOB, may be repeated
+      --exclude-dir=GLOB    don't walk into directories whose name matches GLOB
+      --binary-files=TYPE   for files that aren't text: binary (the default) says whether
+                            they match, text searches them anyway, without-match skips them
+  -a, --text                same as --binary-files=text
+  -I                        same as --binary-files=without-match
+      --encoding=ENC        read files as auto (the default: UTF-16 if they start with a
+                            byte order mark, else UTF-8), utf-8, utf-16le, utf-16be,
+                            latin1 or windows-1252
+  -z, --search-zip          search inside gzip and zlib compressed files
+  -n, --line-number         prefix each line with its line number
+  -b, --byte-offset         prefix each line with the byte offset of its start
+  -o, --only-matching       print only the matching part of each line, one per line
+  -c, --count               print only how many lines were selected in each file
+  -l, --files-with-matches  print only the names of files with selected lines
+  -L, --files-without-match print only the names of files without selected lines
+      --json                print JSON Lines: an object for the start and end of each file,
+                            each selected or context line and the totals at the end
+  -A, --after-context=NUM   print NUM lines of context after each match
+  -B, --before-context=NUM  print NUM lines of context before each match
+  -C, --context=NUM         print NUM lines of context around each match
+      --color=WHEN          highlight matches: auto (the default), always or never
+  -j, --threads=NUM         search NUM files at once (the default is one per CPU)
+      --sort=SORTBY         print files -r finds as they are done (none, the default) or in path order
+      --help                print this help and exit
+  -V, --version             print the version and exit
+
+Everything after `--` is treated as QUERY or PATH, even if it starts with `-`.
+";
+
+/// Why the command line could not be turned into a `Config`.
+#[derive(Debug, PartialEq)]
+pub enum ArgsError {
+    /// `--help` was given; the caller should print `USAGE`.
+    Help,
+    /// `--version` was given.
+    Version,
+    /// An option that doesn't exist, as it was given.
+    UnknownOption(String),
+    /// An option that takes a value was given none.
+    MissingValue(String),
+    /// An option that doesn't take a value was given one, as in `--count=3`.
+    UnexpectedValue(String),
+    /// An option was given a value it can't use, as in `--context=many`.
+    InvalidValue(String, String),
+    /// There was no QUERY, nor any `-e` or `-f`.
+    MissingQuery,
+    /// A `-f` file couldn't be read; holds the path and the reason.
+    PatternFile(String, String),
+    /// A pattern given with `-E` isn't a valid regular expression.
+    Regex(regex::Error),
+}
+
+impl fmt::Display for ArgsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgsError::Help => write!(f, "help requested"),
+            ArgsError::Version => write!(f, "version requested"),
+            ArgsError::UnknownOption(option) => write!(f, "unknown option '{option}'"),
+            ArgsError::MissingValue(option) => write!(f, "option '{option}' requires a value"),
+            ArgsError::UnexpectedValue(option) => write!(f, "option '{option}' doesn't take a value"),
+            ArgsError::InvalidValue(option, value) => write!(f, "invalid value '{value}' for option '{option}'"),
+            ArgsError::MissingQuery => write!(f, "Didn't get a query string"),
+            ArgsError::PatternFile(path, err) => write!(f, "{path}: {err}"),
+            ArgsError::Regex(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for ArgsError {}
+
+impl From<regex::Error> for ArgsError {
+    fn from(err: regex::Error) -> ArgsError {
+        ArgsError::Regex(err)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Opt {
+    IgnoreCase,
+    NoIgnoreCase,
+    SmartCase,
+    Pattern,
+    PatternFile,
+    Regex,
+    WordRegexp,
+    LineRegexp,
+    InvertMatch,
+    Recursive,
+    NoIgnore,
+    Include,
+    Exclude,
+    ExcludeDir,
+    BinaryFiles,
+    Text,
+    SkipBinary,
+    Encoding,
+    SearchZip,
+    LineNumber,
+    ByteOffset,
+    OnlyMatching,
+    Count,
+    FilesWithMatches,
+    FilesWithoutMatch,
+    Json,
+    AfterContext,
+    BeforeContext,
+    Context,
+    Color,
+    Threads,
+    Sort,
+    Help,
+    Version,
+}
+
+/// Every option with its short and long spelling; an empty long one means it has none.
+const OPTIONS: &[(Opt, Option<char>, &str)] = &[
+    (Opt::IgnoreCase, Some('i'), "ignore-case"),
+    (Opt::NoIgnoreCase, None, "no-ignore-case"),
+    (Opt::SmartCase, Some('S'), "smart-case"),
+    (Opt::Pattern, Some('e'), "regexp"),
+    (Opt::PatternFile, Some('f'), "file"),
+    (Opt::Regex, Some('E'), "regex"),
+    (Opt::WordRegexp, Some('w'), "word-regexp"),
+    (Opt::LineRegexp, Some('x'), "line-regexp"),
+    (Opt::InvertMatch, Some('v'), "invert-match"),
+    (Opt::Recursive, Some('r'), "recursive"),
+    (Opt::NoIgnore, None, "no-ignore"),
+    (Opt::Include, None, "include"),
+    (Opt::Exclude, None, "exclude"),
+    (Opt::ExcludeDir, None, "exclude-dir"),
+    (Opt::BinaryFiles, None, "binary-files"),
+    (Opt::Text, Some('a'), "text"),
+    (Opt::SkipBinary, Some('I'), ""),
+    (Opt::Encoding, None, "encoding"),
+    (Opt::SearchZip, Some('z'), "search-zip"),
+    (Opt::LineNumber, Some('n'), "line-number"),
+    (Opt::ByteOffset, Some('b'), "byte-offset"),
+    (Opt::OnlyMatching, Some('o'), "only-matching"),
+    (Opt::Count, Some('c'), "count"),
+    (Opt::FilesWithMatches, Some('l'), "files-with-matches"),
+    (Opt::FilesWithoutMatch, Some('L'), "files-without-match"),
+    (Opt::Json, None, "json"),
+    (Opt::AfterContext, Some('A'), "after-context"),
+    (Opt::BeforeContext, Some('B'), "before-context"),
+    (Opt::Context, Some('C'), "context"),
+    (Opt::Color, None, "color"),
+    (Opt::Threads, Some('j'), "threads"),
+    (Opt::Sort, None, "sort"),
+    (Opt::Help, None, "help"),
+    (Opt::Version, Some('V'), "version"),
+];
+
+impl Opt {
+    fn from_short(c: char) -> Option<Opt> {
+        OPTIONS.iter().find(|(_, short, _)| *short == Some(c)).map(|(opt, _, _)| *opt)
+    }
+
+    fn from_long(name: &str) -> Option<Opt> {
+        OPTIONS.iter().find(|(_, _, long)| !long.is_empty() && *long == name).map(|(opt, _, _)| *opt)
+    }
+
+    fn long_name(self) -> &'static str {
+        OPTIONS.iter().find(|(opt, _, _)| *opt == self).map(|(_, _, long)| *long).unwrap()
+    }
+
+    fn takes_value(self) -> bool {
+        matches!(
+            self,
+            Opt::Pattern
+                | Opt::PatternFile
+                | Opt::AfterContext
+                | Opt::BeforeContext
+                | Opt::Context
+                | Opt::Color
+                | Opt::Threads
+                | Opt::Sort
+                | Opt::Include
+                | Opt::Exclude
+                | Opt::ExcludeDir
+                | Opt::BinaryFiles
+                | Opt::Encoding
+        )
+    }
+}
+
+/// Parses the value of a numeric option.
+fn number(opt: Opt, value: Option<String>) -> Result<usize, ArgsError> {
+    let value = value.unwrap_or_default();
+    value.parse().map_err(|_| ArgsError::InvalidValue(format!("--{}", opt.long_name()), value))
+}
+
+/// Options seen so far, and what they can't decide until the end.
+struct Parsed {
+    config: Config,
+    regex: bool,
+    /// Whether `-e` or `-f` was given, so the first positional argument is a path.
+    explicit_patterns: bool,
+    /// `-C`, for whichever of `-A` and `-B` isn't given, wherever it comes.
+    context: Option<usize>,
+    positional: Vec<String>,
+}
+
+impl Parsed {
+    fn apply(&mut self, opt: Opt, value: Option<String>) -> Result<(), ArgsError> {
+        match opt {
+            Opt::IgnoreCase => {
+                self.config.ignore_case = true;
+                self.config.smart_case = false;
+            }
+            Opt::NoIgnoreCase => {
+                self.config.ignore_case = false;
+                self.config.smart_case = false;
+            }
+            Opt::SmartCase => self.config.smart_case = true,
+            Opt::Pattern => {
+                self.config.patterns.push(value.unwrap_or_default());
+                self.explicit_patterns = true;
+            }
+            Opt::PatternFile => {
+                let path = value.unwrap_or_default();
+                let contents = fs::read_to_string(&path).map_err(|err| ArgsError::PatternFile(path, err.to_string()))?;
+                self.config.patterns.extend(contents.lines().map(str::to_string));
+                self.explicit_patterns = true;
+            }
+            Opt::Regex => self.regex = true,
+            Opt::WordRegexp => self.config.word_regexp = true,
+            Opt::LineRegexp => self.config.line_regexp = true,
+            Opt::InvertMatch => self.config.invert_match = true,
+            Opt::Recursive => self.config.recursive = true,
+            Opt::NoIgnore => self.config.no_ignore = true,
+            Opt::Include => self.config.include.push(Glob::new(&value.unwrap_or_default())),
+            Opt::Exclude => self.config.exclude.push(Glob::new(&value.unwrap_or_default())),
+            Opt::ExcludeDir => self.config.exclude_dir.push(Glob::new(&value.unwrap_or_default())),
+            Opt::LineNumber => self.config.line_number = true,
+            Opt::ByteOffset => self.config.byte_offset = true,
+            Opt::OnlyMatching => self.config.only_matching = true,
+            Opt::Count => self.config.output = OutputMode::Count,
+            Opt::FilesWithMatches => self.config.output = OutputMode::FilesWithMatches,
+            Opt::FilesWithoutMatch => self.config.output = OutputMode::FilesWithoutMatch,
+            Opt::Json => self.config.output = OutputMode::Json,
+            Opt::AfterContext => self.config.after_context = Some(number(opt, value)?),
+            Opt::BeforeContext => self.config.before_context = Some(number(opt, value)?),
+            Opt::Context => self.context = Some(number(opt, value)?),
+            Opt::Color => {
+                self.config.color = match value.as_deref() {
+                    Some("auto") => ColorChoice::Auto,
+                    Some("always") => ColorChoice::Always,
+                    Some("never") => ColorChoice::Never,
+                    _ => return Err(ArgsError::InvalidValue("--color".to_string(), value.unwrap_or_default())),
+                }
+            }
+            Opt::BinaryFiles => {
+                self.config.binary_files = match value.as_deref() {
+                    Some("binary") => BinaryFiles::Binary,
+                    Some("text") => BinaryFiles::Text,
+                    Some("without-match") => BinaryFiles::WithoutMatch,
+                    _ => return Err(ArgsError::InvalidValue("--binary-files".to_string(), value.unwrap_or_default())),
+                }
+            }
+            Opt::Text => self.config.binary_files = BinaryFiles::Text,
+            Opt::SkipBinary => self.config.binary_files = BinaryFiles::WithoutMatch,
+            Opt::Encoding => {
+                let value = value.unwrap_or_default();
+                self.config.encoding =
+                    Encoding::from_name(&value).ok_or(ArgsError::InvalidValue("--encoding".to_string(), value))?;
+            }
+            Opt::SearchZip => self.config.search_zip = true,
+            Opt::Threads => self.config.threads = number(opt, value)?,
+            Opt::Sort => {
+                self.config.sort = match value.as_deref() {
+                    Some("none") => SortBy::None,
+                    Some("path") => SortBy::Path,
+                    _ => return Err(ArgsError::InvalidValue("--sort".to_string(), value.unwrap_or_default())),
+                }
+            }
+            Opt::Help => return Err(ArgsError::Help),
+            Opt::Version => return Err(ArgsError::Version),
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn parse(mut args: impl Iterator<Item = String>) -> Result<Config, ArgsError> {
+    args.next();
+    let mut parsed = Parsed {
+        config: Config { ignore_case: env::var("IGNORE_CASE").is_ok(), ..Config::default() },
+        regex: false,
+        explicit_patterns: false,
+        context: None,
+        positional: Vec::new(),
+    };
+    let mut only_positional = false;
+
+    while let Some(arg) = args.next() {
+        if only_positional || arg == STDIN_PATH || !arg.starts_with('-') {
+            parsed.positional.push(arg);
+        } else if arg == "--" {
+            only_positional = true;
+        } else if let Some(long) = arg.strip_prefix("--") {
+            let (name, inline) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (long, None),
+            };
+            let opt = Opt::from_long(name).ok_or_else(|| ArgsError::UnknownOption(format!("--{name}")))?;
+            let value = match (opt.takes_value(), inline) {
+                (true, Some(value)) => Some(value),
+                (true, None) => Some(args.next().ok_or_else(|| ArgsError::MissingValue(format!("--{name}")))?),
+                (false, Some(_)) => return Err(ArgsError::UnexpectedValue(format!("--{name}"))),
+                (false, None) => None,
+            };
+            parsed.apply(opt, value)?;
+        } else {
+            let flags = &arg[1..];
+            for (i, c) in flags.char_indices() {
+                let opt = Opt::from_short(c).ok_or_else(|| ArgsError::UnknownOption(format!("-{c}")))?;
+                if !opt.takes_value() {
+                    parsed.apply(opt, None)?;
+                    continue;
+                }
+                // The rest of the cluster is the value, as in `-A3`.
+                let rest = &flags[i + c.len_utf8()..];
+                let value = if rest.is_empty() {
+                    args.next().ok_or_else(|| ArgsError::MissingValue(format!("-{c}")))?
+                } else {
+                    rest.to_string()
+                };
+                parsed.apply(opt, Some(value))?;
+                break;
+            }
+        }
+    }
+
+    let mut positional = parsed.positional.into_iter();
+    let mut config = parsed.config;
+    config.after_context = config.after_context.or(parsed.context);
+    config.before_context = config.before_context.or(parsed.context);
+    if !parsed.explicit_patterns {
+        config.patterns.push(positional.next().ok_or(ArgsError::MissingQuery)?);
+    }
+    config.paths = positional.map(PathBuf::from).collect();
+    if config.paths.is_empty() {
+        // There's nothing to walk in standard input.
+        config.paths.push(PathBuf::from(if config.recursive { CURRENT_DIR_PATH } else { STDIN_PATH }));
+    }
+    if config.smart_case {
+        config.ignore_case = !config.patterns.iter().any(|pattern| has_uppercase(pattern, parsed.regex));
+    }
+    if parsed.regex {
+        let flags = Flags { ignore_case: config.ignore_case, ..Flags::default() };
+        config.regex = Some(Regex::any_of(&config.patterns, flags)?);
+    }
+
+    Ok(config)
+}
+
+/// Whether `pattern` has an uppercase letter, not counting escapes like `\W` in a regex.
+fn has_uppercase(pattern: &str, regex: bool) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if regex && c == '\\' {
+            chars.next();
+        } else if c.is_uppercase() {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<Config, ArgsError> {
+        parse(["minigrep"].iter().chain(args).map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn flags_anywhere_and_combined() {
+        let config = parse_args(&["-ri", "so", "poem.txt", "--no-ignore-case", "-"]).unwrap();
+        assert!(config.recursive);
+        assert!(!config.ignore_case);
+        assert_eq!(config.paths, vec![PathBuf::from("poem.txt"), PathBuf::from("-")]);
+
+        let config = parse_args(&["--ignore-case", "-E", "s[o]"]).unwrap();
+        assert!(config.ignore_case);
+        assert_eq!(config.regex.unwrap().patterns(), ["s[o]"]);
+    }
+
+    #[test]
+    fn smart_case() {
+        assert!(parse_args(&["-S", "frog"]).unwrap().ignore_case);
+        assert!(!parse_args(&["--smart-case", "Frog"]).unwrap().ignore_case);
+        assert!(!parse_args(&["-S", "-e", "frog", "-e", "Bog"]).unwrap().ignore_case);
+        assert!(parse_args(&["-SE", "\\Sfrog\\W"]).unwrap().ignore_case);
+        assert!(!parse_args(&["-S", "\\Sfrog\\W"]).unwrap().ignore_case);
+        assert!(parse_args(&["-S", "-i", "Frog"]).unwrap().ignore_case);
+        assert!(!parse_args(&["-i", "-S", "Frog"]).unwrap().ignore_case);
+    }
+
+    #[test]
+    fn option_values() {
+        let config = parse_args(&["-C2", "--after-context=3", "so", "-B", "1"]).unwrap();
+        assert_eq!((config.before_context, config.after_context), (Some(1), Some(3)));
+        let config = parse_args(&["-A", "3", "so", "-C2"]).unwrap();
+        assert_eq!((config.before_context, config.after_context), (Some(2), Some(3)));
+        assert_eq!(parse_args(&["so"]).unwrap().after_context, None);
+        assert_eq!(config.paths, vec![PathBuf::from("-")]);
+        assert_eq!(parse_args(&["-r", "so"]).unwrap().paths, vec![PathBuf::new()]);
+
+        assert_eq!(parse_args(&["so", "-A"]), Err(ArgsError::MissingValue("-A".to_string())));
+        assert_eq!(parse_args(&["--color", "never", "so"]).unwrap().color, ColorChoice::Never);
+        assert_eq!(parse_args(&["--binary-files=without-match", "so"]).unwrap().binary_files, BinaryFiles::WithoutMatch);
+        assert_eq!(parse_args(&["-Ia", "so"]).unwrap().binary_files, BinaryFiles::Text);
+        assert_eq!(parse_args(&["--encoding=Latin-1", "so"]).unwrap().encoding, Encoding::Latin1);
+        assert!(parse_args(&["-zi", "so"]).unwrap().search_zip);
+        assert_eq!(parse_args(&["-c", "--json", "so"]).unwrap().output, OutputMode::Json);
+        assert_eq!(parse_args(&["--=x", "so"]), Err(ArgsError::UnknownOption("--".to_string())));
+        let config = parse_args(&["-j3", "--sort=path", "so"]).unwrap();
+        assert_eq!((config.threads, config.sort), (3, SortBy::Path));
+        let config = parse_args(&["--include=*.rs", "--include", "*.toml", "--exclude-dir=target", "so"]).unwrap();
+        assert_eq!(config.include, vec![Glob::new("*.rs"), Glob::new("*.toml")]);
+        assert_eq!(config.exclude_dir, vec![Glob::new("target")]);
+        assert_eq!(
+            parse_args(&["--color=sometimes", "so"]),
+            Err(ArgsError::InvalidValue("--color".to_string(), "sometimes".to_string()))
+        );
+        assert_eq!(
+            parse_args(&["-nCx", "so"]).unwrap_err().to_string(),
+            "invalid value 'x' for option '--context'"
+        );
+    }
+
+    #[test]
+    fn several_patterns() {
+        let config = parse_args(&["-e", "frog", "poem.txt", "--regexp=-bog", "-eso"]).unwrap();
+        assert_eq!(config.patterns, ["frog", "-bog", "so"]);
+        assert_eq!(config.paths, vec![PathBuf::from("poem.txt")]);
+
+        let config = parse_args(&["-f", "poem.txt", "-e", "frog"]).unwrap();
+        assert_eq!(config.patterns.len(), 10);
+        assert_eq!(config.patterns[0], "I'm nobody! Who are you?");
+        assert_eq!(config.patterns[9], "frog");
+
+        assert!(matches!(parse_args(&["-f", "no-such-file"]), Err(ArgsError::PatternFile(..))));
+    }
+
+    #[test]
+    fn double_dash_ends_options() {
+        let config = parse_args(&["--", "-r", "--help"]).unwrap();
+        assert_eq!(config.patterns, ["-r"]);
+        assert_eq!(config.paths, vec![PathBuf::from("--help")]);
+        assert!(!config.recursive);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse_args(&["--help", "so"]), Err(ArgsError::Help));
+        assert_eq!(parse_args(&["-V"]), Err(ArgsError::Version));
+        assert_eq!(parse_args(&["-rq", "so"]), Err(ArgsError::UnknownOption("-q".to_string())));
+        assert_eq!(parse_args(&["--frog", "so"]).unwrap_err().to_string(), "unknown option '--frog'");
+        assert_eq!(parse_args(&["--regex=yes", "so"]), Err(ArgsError::UnexpectedValue("--regex".to_string())));
+        assert_eq!(parse_args(&["-i"]), Err(ArgsError::MissingQuery));
+    }
+}
@@ -0,0 +1,98 @@
+//! Unicode case folding, one character at a time and without allocating.
+//!
+//! Folding maps every case variant of a string to the same form, which isn't
+//! always one character long: 'ß', 'ẞ' and "SS" all fold to "ss", and 'ﬁ' to
+//! "fi". This follows the default, not the Turkic, folding, so 'İ' folds to
+//! "i̇" and the dotless 'ı' only ever matches itself.
+
+/// The folded form of a single character, at most three characters long.
+#[derive(Debug, Clone)]
+pub(crate) struct Fold {
+    chars: [char; 3],
+    len: usize,
+    next: usize,
+}
+
+impl Fold {
+    /// A character that folds to `c` alone.
+    pub(crate) fn one(c: char) -> Fold {
+        Fold { chars: [c, '\0', '\0'], len: 1, next: 0 }
+    }
+
+    fn push(&mut self, c: char) {
+        self.chars[self.len] = c;
+        self.len += 1;
+    }
+}
+
+impl Iterator for Fold {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars[..self.len].get(self.next).copied();
+        self.next += 1;
+        c
+    }
+}
+
+/// Folds `c`, which is uppercasing and then lowercasing it apart from a couple
+/// of characters that round trip wrongly.
+pub(crate) fn fold(c: char) -> Fold {
+    if c.is_ascii() {
+        return Fold::one(c.to_ascii_lowercase());
+    }
+    match c {
+        // Going through 'I' would make the dotless 'ı' fold like 'i'.
+        'ı' => return Fold::one(c),
+        // Lowercases to 'ß', which only folds to "ss" on a second pass.
+        'ẞ' => return Fold { chars: ['s', 's', '\0'], len: 2, next: 0 },
+        _ => {}
+    }
+
+    let mut folded = Fold { chars: ['\0'; 3], len: 0, next: 0 };
+    for upper in c.to_uppercase() {
+        for lower in upper.to_lowercase() {
+            folded.push(lower);
+        }
+    }
+    folded
+}
+
+/// Folds `c` to a single character, leaving it alone if its full folding is longer.
+pub(crate) fn simple_fold(c: char) -> char {
+    let mut folded = fold(c);
+    match (folded.next(), folded.next()) {
+        (Some(f), None) => f,
+        _ if c == 'ẞ' => 'ß',
+        _ => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fold_str(s: &str) -> String {
+        s.chars().flat_map(fold).collect()
+    }
+
+    #[test]
+    fn multi_character_folds() {
+        assert_eq!(fold_str("Straße"), "strasse");
+        assert_eq!(fold_str("STRASSE"), "strasse");
+        assert_eq!(fold_str("ẞ"), "ss");
+        assert_eq!(fold_str("ﬁne ﬄow"), "fine fflow");
+        assert_eq!(fold_str("İ"), "i\u{307}");
+        assert_eq!(fold_str("ΐ"), "\u{3b9}\u{308}\u{301}");
+    }
+
+    #[test]
+    fn single_character_folds() {
+        assert_eq!(fold_str("ΣΑΣ σας"), "σασ σασ");
+        assert_eq!(fold_str("\u{212a}elvin ſ"), "kelvin s");
+        assert_eq!(fold_str("ıI"), "ıi");
+        assert_eq!(simple_fold('ß'), 'ß');
+        assert_eq!(simple_fold('ẞ'), 'ß');
+        assert_eq!(simple_fold('Ω'), 'ω');
+    }
+}
@@ -0,0 +1,242 @@
+//! Searching inside compressed files, for `-z`.
+//!
+//! Formats are told apart by the magic bytes they start with, not by file
+//! name. gzip (including several members one after another) and zlib are
+//! decompressed; bzip2, xz and zstd are recognised but give an error.
+
+use std::io::{self, BufRead, Read};
+
+use crate::inflate::{BitReader, Inflate};
+
+/// Wraps `reader` in a decompressor if what it holds starts like a format this
+/// knows, or returns it as it is if not.
+pub fn decompress<'a, R: BufRead + 'a>(mut reader: R) -> io::Result<Box<dyn Read + 'a>> {
+    let start = reader.fill_buf()?;
+    let unsupported = match start {
+        [0x1f, 0x8b, ..] => return Ok(Box::new(GzipReader::new(reader))),
+        // The usual zlib headers; others would be taken for text too often.
+        [0x78, 0x01 | 0x9c | 0xda, ..] => return Ok(Box::new(ZlibReader::new(reader))),
+        [b'B', b'Z', b'h', b'1'..=b'9', 0x31, 0x41, 0x59, ..] => "bzip2",
+        [0xfd, b'7', b'z', b'X', b'Z', 0x00, ..] => "xz",
+        [0x28, 0xb5, 0x2f, 0xfd, ..] => "zstd",
+        _ => return Ok(Box::new(reader)),
+    };
+    Err(io::Error::new(io::ErrorKind::Unsupported, format!("{unsupported} compression is not supported")))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Reads what is compressed in gzip members (RFC 1952), checking each one's CRC-32.
+struct GzipReader<R> {
+    inflate: Inflate<R>,
+    in_member: bool,
+    crc: u32,
+    size: u32,
+}
+
+impl<R: Read> GzipReader<R> {
+    fn new(inner: R) -> GzipReader<R> {
+        GzipReader { inflate: Inflate::new(BitReader::new(inner)), in_member: false, crc: !0, size: 0 }
+    }
+
+    /// Reads the header of the next member, or returns `false` if there isn't one.
+    fn read_header(&mut self) -> io::Result<bool> {
+        const FHCRC: u8 = 0x02;
+        const FEXTRA: u8 = 0x04;
+        const FNAME: u8 = 0x08;
+        const FCOMMENT: u8 = 0x10;
+
+        let input = self.inflate.input();
+        match input.aligned_byte()? {
+            Some(0x1f) => {}
+            // Anything else after the first member, such as zero padding, is ignored.
+            _ => return Ok(false),
+        }
+        let mut header = [0; 9];
+        input.aligned_bytes(&mut header)?;
+        let [id2, method, flags, ..] = header;
+        if id2 != 0x8b {
+            return Ok(false);
+        }
+        if method != 8 {
+            return Err(invalid("unknown gzip compression method"));
+        }
+
+        if flags & FEXTRA != 0 {
+            let mut len = [0; 2];
+            input.aligned_bytes(&mut len)?;
+            input.aligned_bytes(&mut vec![0; usize::from(u16::from_le_bytes(len))])?;
+        }
+        for flag in [FNAME, FCOMMENT] {
+            if flags & flag != 0 {
+                // Zero-terminated.
+                let mut byte = [0xff];
+                while byte[0] != 0 {
+                    input.aligned_bytes(&mut byte)?;
+                }
+            }
+        }
+        if flags & FHCRC != 0 {
+            input.aligned_bytes(&mut [0; 2])?;
+        }
+        Ok(true)
+    }
+
+    fn read_trailer(&mut self) -> io::Result<()> {
+        let mut trailer = [0; 8];
+        self.inflate.input().aligned_bytes(&mut trailer)?;
+        let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+        let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+        if crc != !self.crc || size != self.size {
+            return Err(invalid("gzip checksum doesn't match"));
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for GzipReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if !self.in_member {
+                if !self.read_header()? {
+                    return Ok(0);
+                }
+                self.in_member = true;
+                self.crc = !0;
+                self.size = 0;
+            }
+            let n = self.inflate.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                self.crc = crc32(self.crc, &buf[..n]);
+                self.size = self.size.wrapping_add(n as u32);
+                return Ok(n);
+            }
+            self.read_trailer()?;
+            self.in_member = false;
+            self.inflate.restart();
+        }
+    }
+}
+
+/// Reads what is compressed in a zlib stream (RFC 1950), checking its Adler-32.
+struct ZlibReader<R> {
+    inflate: Inflate<R>,
+    started: bool,
+    finished: bool,
+    adler: (u32, u32),
+}
+
+impl<R: Read> ZlibReader<R> {
+    fn new(inner: R) -> ZlibReader<R> {
+        ZlibReader { inflate: Inflate::new(BitReader::new(inner)), started: false, finished: false, adler: (1, 0) }
+    }
+}
+
+impl<R: Read> Read for ZlibReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.started {
+            let mut header = [0; 2];
+            self.inflate.input().aligned_bytes(&mut header)?;
+            if header[1] & 0x20 != 0 {
+                return Err(invalid("zlib preset dictionaries are not supported"));
+            }
+            self.started = true;
+        }
+        if self.finished {
+            return Ok(0);
+        }
+
+        let n = self.inflate.read(buf)?;
+        let (mut a, mut b) = self.adler;
+        // Small enough chunks that the sums can't overflow before being reduced.
+        for chunk in buf[..n].chunks(5552) {
+            for &byte in chunk {
+                a += u32::from(byte);
+                b += a;
+            }
+            a %= 65521;
+            b %= 65521;
+        }
+        self.adler = (a, b);
+
+        if n == 0 && !buf.is_empty() {
+            let mut trailer = [0; 4];
+            self.inflate.input().aligned_bytes(&mut trailer)?;
+            if u32::from_be_bytes(trailer) != (b << 16 | a) {
+                return Err(invalid("zlib checksum doesn't match"));
+            }
+            self.finished = true;
+        }
+        Ok(n)
+    }
+}
+
+/// The CRC-32 lookup table for the reflected polynomial 0xedb88320.
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { 0xedb88320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Continues a CRC-32 kept inverted, as it is between calls.
+fn crc32(crc: u32, bytes: &[u8]) -> u32 {
+    bytes.iter().fold(crc, |crc, &byte| CRC_TABLE[usize::from(crc as u8 ^ byte)] ^ (crc >> 8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// "a frog\n", from gzip.
+    const GZIP: [u8; 27] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x4b, 0x54, 0x48, 0x2b, 0xca, 0x4f, 0xe7, 0x02, 0x00,
+        0x80, 0xef, 0xa7, 0xe9, 0x07, 0x00, 0x00, 0x00,
+    ];
+
+    fn read(bytes: &[u8]) -> io::Result<String> {
+        let mut out = String::new();
+        decompress(bytes)?.read_to_string(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(read(&GZIP).unwrap(), "a frog\n");
+        assert_eq!(read(&[GZIP, GZIP].concat()).unwrap(), "a frog\na frog\n");
+        // With a file name, and zero padding after it.
+        let mut named = GZIP[..10].to_vec();
+        named[3] = 0x08;
+        named.extend_from_slice(b"frog.txt\0");
+        named.extend_from_slice(&GZIP[10..]);
+        named.extend_from_slice(&[0; 4]);
+        assert_eq!(read(&named).unwrap(), "a frog\n");
+
+        let zlib = [0x78, 0x9c, 0x4b, 0x54, 0x48, 0x2b, 0xca, 0x4f, 0xe7, 0x02, 0x00, 0x09, 0x59, 0x02, 0x3a];
+        assert_eq!(read(&zlib).unwrap(), "a frog\n");
+
+        assert_eq!(read(b"x^2 frog\n").unwrap(), "x^2 frog\n");
+        assert_eq!(read(b"").unwrap(), "");
+        let xz = read(&[0xfd, b'7', b'z', b'X', b'Z', 0, 0, 4]).unwrap_err();
+        assert_eq!(xz.to_string(), "xz compression is not supported");
+    }
+
+    #[test]
+    fn corrupt_input() {
+        let mut bad_crc = GZIP;
+        bad_crc[20] ^= 1;
+        assert_eq!(read(&bad_crc).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(read(&GZIP[..15]).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,317 @@
+//! Turning text in other encodings into UTF-8 before it is searched.
+
+use std::io::{self, Read};
+use std::sync::{Arc, Mutex};
+
+/// The values of `--encoding`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    /// UTF-16 if the input starts with a UTF-16 byte order mark, UTF-8 otherwise.
+    #[default]
+    Auto,
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// ISO-8859-1, where every byte is the code point of the same number.
+    Latin1,
+    /// Latin-1 with printable characters such as '€' and '“' in place of most of
+    /// the C1 controls.
+    Windows1252,
+}
+
+impl Encoding {
+    /// Looks up an encoding by one of its usual names, ignoring case.
+    pub fn from_name(name: &str) -> Option<Encoding> {
+        let encoding = match name.to_ascii_lowercase().as_str() {
+            "auto" => Encoding::Auto,
+            "utf-8" | "utf8" => Encoding::Utf8,
+            "utf-16le" | "utf16le" => Encoding::Utf16Le,
+            "utf-16be" | "utf16be" => Encoding::Utf16Be,
+            "latin1" | "latin-1" | "iso-8859-1" => Encoding::Latin1,
+            "windows-1252" | "cp1252" => Encoding::Windows1252,
+            _ => return None,
+        };
+        Some(encoding)
+    }
+}
+
+/// What Windows-1252 has at 0x80 to 0x9f; the five bytes it leaves undefined
+/// are taken as the Latin-1 controls.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}', //
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+
+/// Where the text a `DecodeReader` hands out is in its input, so that offsets in
+/// the decoded text can be taken back to where they are in the input. Clones
+/// share the same map, which fills in as the reader goes.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RawOffsets(Arc<Mutex<OffsetMap>>);
+
+#[derive(Debug, Default)]
+struct OffsetMap {
+    /// Whether each byte of ASCII text took two in the input, rather than one.
+    utf16: bool,
+    /// Where each character that didn't take the usual number of bytes in the input
+    /// ends in the decoded text and in the input, after a byte order mark if any.
+    ends: Vec<(usize, usize)>,
+}
+
+impl RawOffsets {
+    /// Takes an offset in the decoded text to the offset it stands for in the input.
+    pub(crate) fn raw_offset(&self, offset: usize) -> usize {
+        let map = self.0.lock().unwrap();
+        let scale = if map.utf16 { 2 } else { 1 };
+        match map.ends.partition_point(|&(decoded, _)| decoded <= offset) {
+            0 => offset * scale,
+            i => {
+                let (decoded, raw) = map.ends[i - 1];
+                raw + (offset - decoded) * scale
+            }
+        }
+    }
+
+    /// Lets go of what is only needed for offsets before `offset`, which won't be
+    /// asked about again.
+    pub(crate) fn forget_before(&self, offset: usize) {
+        let mut map = self.0.lock().unwrap();
+        let before = map.ends.partition_point(|&(decoded, _)| decoded <= offset);
+        map.ends.drain(..before.saturating_sub(1));
+    }
+}
+
+/// Reads UTF-8 from a reader in some other encoding.
+///
+/// A byte order mark at the start is dropped, and one for UTF-16 decides the
+/// encoding under `Encoding::Auto`. Anything that can't be decoded becomes
+/// U+FFFD; UTF-8 itself is passed through untouched, invalid or not.
+pub struct DecodeReader<R> {
+    inner: R,
+    encoding: Encoding,
+    sniffed: bool,
+    /// Bytes read from `inner` but not decoded yet.
+    raw: Vec<u8>,
+    /// Decoded bytes not yet handed out, from `decoded_pos` on.
+    decoded: Vec<u8>,
+    decoded_pos: usize,
+    /// How many bytes have been decoded, and how many of the input's went into them.
+    decoded_total: usize,
+    raw_total: usize,
+    offsets: RawOffsets,
+}
+
+impl<R: Read> DecodeReader<R> {
+    pub fn new(inner: R, encoding: Encoding) -> DecodeReader<R> {
+        DecodeReader {
+            inner,
+            encoding,
+            sniffed: false,
+            raw: Vec::new(),
+            decoded: Vec::new(),
+            decoded_pos: 0,
+            decoded_total: 0,
+            raw_total: 0,
+            offsets: RawOffsets::default(),
+        }
+    }
+
+    /// Where what this reads is in its input.
+    pub(crate) fn raw_offsets(&self) -> RawOffsets {
+        self.offsets.clone()
+    }
+
+    /// Reads far enough to see any byte order mark, and settles on an encoding.
+    fn sniff(&mut self) -> io::Result<()> {
+        let mut start = [0; 3];
+        let mut len = 0;
+        while len < start.len() {
+            match self.inner.read(&mut start[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        let start = &start[..len];
+
+        let (bom, encoding) = match (start, self.encoding) {
+            ([0xef, 0xbb, 0xbf, ..], Encoding::Auto | Encoding::Utf8) => (3, Encoding::Utf8),
+            ([0xff, 0xfe, ..], Encoding::Auto | Encoding::Utf16Le) => (2, Encoding::Utf16Le),
+            ([0xfe, 0xff, ..], Encoding::Auto | Encoding::Utf16Be) => (2, Encoding::Utf16Be),
+            (_, Encoding::Auto) => (0, Encoding::Utf8),
+            (_, encoding) => (0, encoding),
+        };
+        self.encoding = encoding;
+        self.raw.extend_from_slice(&start[bom..]);
+        let mut map = self.offsets.0.lock().unwrap();
+        map.utf16 = matches!(encoding, Encoding::Utf16Le | Encoding::Utf16Be);
+        if bom > 0 {
+            map.ends.push((0, bom));
+        }
+        self.raw_total = bom;
+        self.sniffed = true;
+        Ok(())
+    }
+
+    /// Decodes what is in `raw`, keeping back a trailing partial character unless
+    /// the input has ended.
+    fn decode(&mut self, at_end: bool) {
+        self.decoded.clear();
+        self.decoded_pos = 0;
+        let mut map = self.offsets.0.lock().unwrap();
+        let mut ends = Ends { ends: &mut map.ends, decoded: self.decoded_total, raw: self.raw_total };
+        let used = match self.encoding {
+            Encoding::Auto | Encoding::Utf8 => {
+                self.decoded.extend_from_slice(&self.raw);
+                self.raw.len()
+            }
+            Encoding::Latin1 => {
+                for &b in &self.raw {
+                    ends.push(&mut self.decoded, char::from(b), 1);
+                }
+                self.raw.len()
+            }
+            Encoding::Windows1252 => {
+                for &b in &self.raw {
+                    let c = match b {
+                        0x80..=0x9f => WINDOWS_1252_HIGH[usize::from(b - 0x80)],
+                        _ => char::from(b),
+                    };
+                    ends.push(&mut self.decoded, c, 1);
+                }
+                self.raw.len()
+            }
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let unit = |pair: &[u8]| match self.encoding {
+                    Encoding::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+                    _ => u16::from_be_bytes([pair[0], pair[1]]),
+                };
+                let mut units: Vec<u16> = self.raw.chunks_exact(2).map(unit).collect();
+                // A high surrogate needs the unit after it, which may not have been read yet.
+                if !at_end && units.last().is_some_and(|&u| (0xd800..0xdc00).contains(&u)) {
+                    units.pop();
+                }
+                for c in char::decode_utf16(units.iter().copied()) {
+                    match c {
+                        Ok(c) => ends.push(&mut self.decoded, c, c.len_utf16() * 2),
+                        Err(_) => ends.push(&mut self.decoded, char::REPLACEMENT_CHARACTER, 2),
+                    }
+                }
+                let used = units.len() * 2;
+                if at_end && used < self.raw.len() {
+                    ends.push(&mut self.decoded, char::REPLACEMENT_CHARACTER, self.raw.len() - used);
+                    self.raw.len()
+                } else {
+                    used
+                }
+            }
+        };
+        self.decoded_total += self.decoded.len();
+        self.raw_total += used;
+        self.raw.drain(..used);
+    }
+}
+
+/// Keeps track of where decoded characters end, for `OffsetMap::ends`.
+struct Ends<'a> {
+    ends: &'a mut Vec<(usize, usize)>,
+    decoded: usize,
+    raw: usize,
+}
+
+impl Ends<'_> {
+    /// Adds `c`, which took `raw_len` bytes of the input, to `decoded`.
+    fn push(&mut self, decoded: &mut Vec<u8>, c: char, raw_len: usize) {
+        decoded.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+        self.decoded += c.len_utf8();
+        self.raw += raw_len;
+        // ASCII always takes the usual one byte, or two in UTF-16.
+        if !c.is_ascii() {
+            self.ends.push((self.decoded, self.raw));
+        }
+    }
+}
+
+impl<R: Read> Read for DecodeReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.sniffed {
+            self.sniff()?;
+        }
+        loop {
+            if self.decoded_pos < self.decoded.len() {
+                let n = buf.len().min(self.decoded.len() - self.decoded_pos);
+                buf[..n].copy_from_slice(&self.decoded[self.decoded_pos..self.decoded_pos + n]);
+                self.decoded_pos += n;
+                return Ok(n);
+            }
+            // Plain UTF-8 doesn't need to go through the buffers at all.
+            if self.raw.is_empty() && matches!(self.encoding, Encoding::Auto | Encoding::Utf8) {
+                return self.inner.read(buf);
+            }
+
+            let mut chunk = [0; 8 * 1024];
+            let n = self.inner.read(&mut chunk)?;
+            if n == 0 && self.raw.is_empty() {
+                return Ok(0);
+            }
+            self.raw.extend_from_slice(&chunk[..n]);
+            self.decode(n == 0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(bytes: &[u8], encoding: Encoding) -> String {
+        let mut decoded = String::new();
+        DecodeReader::new(bytes, encoding).read_to_string(&mut decoded).unwrap();
+        decoded
+    }
+
+    #[test]
+    fn byte_order_marks() {
+        let le: Vec<u8> = [0xff, 0xfe].into_iter().chain("frog 🐸\n".encode_utf16().flat_map(u16::to_le_bytes)).collect();
+        let be: Vec<u8> = [0xfe, 0xff].into_iter().chain("frog 🐸\n".encode_utf16().flat_map(u16::to_be_bytes)).collect();
+        assert_eq!(decode(&le, Encoding::Auto), "frog 🐸\n");
+        assert_eq!(decode(&be, Encoding::Auto), "frog 🐸\n");
+        assert_eq!(decode(&le[2..], Encoding::Utf16Le), "frog 🐸\n");
+        assert_eq!(decode(b"\xef\xbb\xbffrog", Encoding::Auto), "frog");
+        assert_eq!(decode(b"fr", Encoding::Auto), "fr");
+        assert_eq!(decode(b"", Encoding::Auto), "");
+
+        // Cut off in the middle of a surrogate pair.
+        assert_eq!(decode(&le[..le.len() - 4], Encoding::Auto), "frog \u{fffd}");
+        assert_eq!(decode(&le[..le.len() - 1], Encoding::Auto), "frog 🐸\u{fffd}");
+    }
+
+    #[test]
+    fn single_byte_encodings() {
+        assert_eq!(decode(b"caf\xe9 \x80", Encoding::Latin1), "café \u{80}");
+        assert_eq!(decode(b"caf\xe9 \x80 \x93hi\x94 \x81", Encoding::Windows1252), "café € “hi” \u{81}");
+        assert_eq!(Encoding::from_name("CP1252"), Some(Encoding::Windows1252));
+        assert_eq!(Encoding::from_name("ebcdic"), None);
+    }
+
+    #[test]
+    fn long_input() {
+        let text = "ünïcödé line\n".repeat(2000);
+        let utf16: Vec<u8> = [0xfe, 0xff].into_iter().chain(text.encode_utf16().flat_map(u16::to_be_bytes)).collect();
+        let mut decoded = String::new();
+        // Odd-sized reads, so pairs of bytes get split.
+        let reader = io::BufReader::with_capacity(7, &utf16[..]);
+        let mut decoder = DecodeReader::new(reader, Encoding::Auto);
+        let raw_offsets = decoder.raw_offsets();
+        decoder.read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, text);
+
+        // Each line is 17 bytes decoded and 26 in the input, after the 2 of the byte order mark.
+        for line in [0, 1, 1000, 1999] {
+            assert_eq!(raw_offsets.raw_offset(line * 17), 2 + line * 26);
+            assert_eq!(raw_offsets.raw_offset(line * 17 + 9), 2 + line * 26 + 12);
+            raw_offsets.forget_before(line * 17);
+        }
+        assert!(raw_offsets.0.lock().unwrap().ends.len() < 10);
+    }
+}
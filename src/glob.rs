@@ -0,0 +1,323 @@
+//! Shell-style wildcard patterns for matching paths, as used by ignore files
+//! and by `--include`, `--exclude` and `--exclude-dir`.
+
+use std::iter;
+use std::path::{Component, Path};
+
+/// A compiled pattern, matched against paths with `/` between components.
+///
+/// ```text
+/// ?           any character but `/`
+/// *           any run of characters without a `/`
+/// **          any run of characters at all, when it makes up a whole path
+///             component: `**/a` matches `a` in any directory and `a/**`
+///             everything inside `a`
+/// [...]       one character from a class, [!...] or [^...] negated, ranges like a-z
+/// {a,b}       either of the comma-separated alternatives, which can nest
+/// \x          the character x taken literally
+/// ```
+///
+/// Anything else matches itself, including a `[` or `{` that is never closed.
+///
+/// # Examples
+///
+/// ```
+/// use minigrep::Glob;
+///
+/// let glob = Glob::new("*.{rs,toml}");
+/// assert!(glob.is_match("main.rs"));
+/// assert!(!glob.is_match("src/main.rs"));
+/// assert!(glob.is_match_path("./src/main.rs".as_ref()));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Glob {
+    pattern: String,
+    tokens: Vec<Token>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Char(char),
+    Any,
+    Star,
+    /// `**/`: nothing, or any number of whole directories.
+    AnyDirs,
+    /// `**` at the end: everything that is left.
+    AnyPath,
+    Class { negated: bool, ranges: Vec<(char, char)> },
+    /// `{a,b}`: any one of the lists of tokens.
+    Alternatives(Vec<Vec<Token>>),
+}
+
+impl Glob {
+    /// Compiles `pattern`, which can't fail: anything that isn't valid syntax is taken literally.
+    pub fn new(pattern: &str) -> Glob {
+        let chars: Vec<char> = pattern.chars().collect();
+        Glob { pattern: pattern.to_string(), tokens: tokenize(&chars, true, true) }
+    }
+
+    /// The pattern as it was given.
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Whether the whole of `path` matches.
+    pub fn is_match(&self, path: &str) -> bool {
+        let text: Vec<char> = path.chars().collect();
+        let mut reachable = vec![false; text.len() + 1];
+        reachable[0] = true;
+        advance(&self.tokens, reachable, &text)[text.len()]
+    }
+
+    /// Whether the file name of `path` matches, or all of it if the pattern has a
+    /// `/` in it. `.` components are left out, so `*/main.rs` matches `./src/main.rs`.
+    pub fn is_match_path(&self, path: &Path) -> bool {
+        let components: Vec<_> = path
+            .components()
+            .filter(|component| *component != Component::CurDir)
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect();
+        if self.pattern.contains('/') {
+            self.is_match(&components.join("/"))
+        } else {
+            components.last().is_some_and(|name| self.is_match(name))
+        }
+    }
+}
+
+/// Turns a pattern, or one of the alternatives in braces in it, into tokens. For
+/// telling what a `**` at either end of `chars` is, `after_slash` says whether
+/// they start the pattern or follow a `/`, and `at_end` whether they end it.
+fn tokenize(chars: &[char], after_slash: bool, at_end: bool) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '?' => tokens.push(Token::Any),
+            '*' => {
+                let stars = chars[i..].iter().take_while(|&&c| c == '*').count();
+                let starts_component = if i == 0 { after_slash } else { chars[i - 1] == '/' };
+                i += stars - 1;
+                if stars == 1 || !starts_component {
+                    tokens.push(Token::Star);
+                } else if chars.get(i + 1) == Some(&'/') {
+                    tokens.push(Token::AnyDirs);
+                    i += 1;
+                } else if i + 1 == chars.len() && at_end {
+                    tokens.push(Token::AnyPath);
+                } else {
+                    tokens.push(Token::Star);
+                }
+            }
+            '[' => match parse_class(&chars[i + 1..]) {
+                Some((class, len)) => {
+                    tokens.push(class);
+                    i += len;
+                }
+                None => tokens.push(Token::Char('[')),
+            },
+            '{' => match parse_braces(&chars[i..]) {
+                Some((alternatives, len)) => {
+                    let after_slash = if i == 0 { after_slash } else { chars[i - 1] == '/' };
+                    let at_end = at_end && i + len == chars.len();
+                    let alternatives = alternatives.iter().map(|chars| tokenize(chars, after_slash, at_end));
+                    tokens.push(Token::Alternatives(alternatives.collect()));
+                    i += len - 1;
+                }
+                None => tokens.push(Token::Char('{')),
+            },
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                tokens.push(Token::Char(chars[i]));
+            }
+            c => tokens.push(Token::Char(c)),
+        }
+        i += 1;
+    }
+    tokens
+}
+
+/// Takes where in `text` the tokens before `tokens` can have matched up to, with
+/// `reachable[i]` meaning exactly `text[..i]`, to where `tokens` can take that.
+fn advance(tokens: &[Token], mut reachable: Vec<bool>, text: &[char]) -> Vec<bool> {
+    for token in tokens {
+        let mut next = vec![false; text.len() + 1];
+        if let Token::Alternatives(alternatives) = token {
+            for alternative in alternatives {
+                let ends = advance(alternative, reachable.clone(), text);
+                next.iter_mut().zip(ends).for_each(|(next, end)| *next |= end);
+            }
+            reachable = next;
+            continue;
+        }
+        for i in (0..=text.len()).filter(|&i| reachable[i]) {
+            match token {
+                Token::Char(_) | Token::Any | Token::Class { .. } => {
+                    if text.get(i).is_some_and(|&c| token.matches_one(c)) {
+                        next[i + 1] = true;
+                    }
+                }
+                Token::Star => {
+                    next[i] = true;
+                    for j in (i..text.len()).take_while(|&j| text[j] != '/') {
+                        next[j + 1] = true;
+                    }
+                }
+                Token::AnyDirs => {
+                    next[i] = true;
+                    for j in (i..text.len()).filter(|&j| text[j] == '/') {
+                        next[j + 1] = true;
+                    }
+                }
+                Token::AnyPath => next[i..].fill(true),
+                Token::Alternatives(_) => unreachable!("handled before looking at positions"),
+            }
+        }
+        reachable = next;
+    }
+    reachable
+}
+
+/// Parses braces from their `{`, returning the alternatives between them and how
+/// many characters they took, including the `}`. There have to be at least two,
+/// and commas and braces inside a further pair of braces are part of those.
+fn parse_braces(chars: &[char]) -> Option<(Vec<&[char]>, usize)> {
+    let mut depth = 0;
+    let mut commas = Vec::new();
+    let mut i = 1;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '{' => depth += 1,
+            '}' if depth > 0 => depth -= 1,
+            '}' if !commas.is_empty() => {
+                let starts = iter::once(0).chain(commas.iter().copied());
+                let ends = commas.iter().copied().chain([i]);
+                return Some((starts.zip(ends).map(|(start, end)| &chars[start + 1..end]).collect(), i + 1));
+            }
+            '}' => return None,
+            ',' if depth == 0 => commas.push(i),
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+impl Token {
+    /// For the tokens that match a single character, whether they match `c`.
+    fn matches_one(&self, c: char) -> bool {
+        match self {
+            Token::Char(expected) => c == *expected,
+            Token::Any => c != '/',
+            Token::Class { negated, ranges } => c != '/' && ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != *negated,
+            Token::Star | Token::AnyDirs | Token::AnyPath | Token::Alternatives(_) => false,
+        }
+    }
+}
+
+/// Parses a class from just after its `[`, returning it along with how many
+/// characters it took including the closing `]`.
+fn parse_class(chars: &[char]) -> Option<(Token, usize)> {
+    let negated = matches!(chars.first(), Some('!' | '^'));
+    let mut i = usize::from(negated);
+    let mut ranges = Vec::new();
+
+    // A `]` straight after the `[` is part of the class.
+    let mut first = true;
+    loop {
+        let mut c = *chars.get(i)?;
+        if c == ']' && !first {
+            return Some((Token::Class { negated, ranges }, i + 1));
+        }
+        first = false;
+        if c == '\\' {
+            i += 1;
+            c = *chars.get(i)?;
+        }
+        match (chars.get(i + 1), chars.get(i + 2)) {
+            (Some('-'), Some(&hi)) if hi != ']' => {
+                ranges.push((c, hi));
+                i += 3;
+            }
+            _ => {
+                ranges.push((c, c));
+                i += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        Glob::new(pattern).is_match(path)
+    }
+
+    #[test]
+    fn wildcards_and_classes() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(matches("*.rs", ".rs"));
+        assert!(!matches("*.rs", "src/main.rs"));
+        assert!(matches("src/*.rs", "src/main.rs"));
+        assert!(matches("ma?n.rs", "main.rs"));
+        assert!(!matches("a?b", "a/b"));
+        assert!(matches("[a-c]x[!0-9]", "bxy"));
+        assert!(!matches("[a-c]x[!0-9]", "bx7"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("a[b", "a[b"));
+        assert!(matches("\\*\\?", "*?"));
+        assert!(!matches("\\*", "x"));
+    }
+
+    #[test]
+    fn double_stars() {
+        assert!(matches("**/target", "target"));
+        assert!(matches("**/target", "a/b/target"));
+        assert!(matches("a/**/b", "a/b"));
+        assert!(matches("a/**/b", "a/x/y/b"));
+        assert!(!matches("a/**/b", "a/xb"));
+        assert!(matches("a/**", "a/x/y"));
+        assert!(!matches("a/**", "b/x"));
+        assert!(matches("**", "any/thing"));
+        // Not a whole component, so just a `*`.
+        assert!(matches("a**b", "axxb"));
+        assert!(!matches("a**b", "a/b"));
+    }
+
+    #[test]
+    fn braces() {
+        assert!(matches("*.{rs,toml}", "Cargo.toml"));
+        assert!(matches("*.{rs,toml}", "main.rs"));
+        assert!(!matches("*.{rs,toml}", "main.js"));
+        assert!(matches("{src,benches}/**/*.rs", "benches/search.rs"));
+        assert!(matches("a{b,c{d,e}}f", "acef"));
+        assert!(matches("a{,.min}.js", "a.js"));
+        assert!(matches("a{b}", "a{b}"));
+        assert!(matches("a{b,c", "a{b,c"));
+        assert!(matches("\\{a,b}", "{a,b}"));
+        assert!(matches("a{\\,,b}c", "a,c"));
+        assert!(matches("x/{**/a,b}", "x/y/z/a"));
+        assert!(matches("{a,b/**}", "b/c/d"));
+        assert!(!matches("{a,b**}", "b/c"));
+
+        // Each group is matched in place rather than written out every way it can be.
+        let pattern = "{a,b}".repeat(40);
+        assert!(matches(&pattern, &"ab".repeat(20)));
+        assert!(!matches(&pattern, &"ab".repeat(19)));
+    }
+
+    #[test]
+    fn names_and_paths() {
+        let glob = Glob::new("*.min.js");
+        assert!(glob.is_match_path(Path::new("./web/app.min.js")));
+        assert!(!glob.is_match_path(Path::new("app.min.js/index.js")));
+
+        let glob = Glob::new("web/*.js");
+        assert!(glob.is_match_path(Path::new("./web/app.js")));
+        assert!(!glob.is_match_path(Path::new("lib/web/app.js")));
+    }
+}
@@ -0,0 +1,188 @@
+//! Ignore files, which keep `-r` away from what a repository doesn't track.
+//!
+//! `.gitignore`, `.ignore` and `.minigrepignore` all use the gitignore format:
+//! one glob per line, `#` for comments, `!` to include again something an
+//! earlier line ignored, a trailing `/` to only match directories, and a `/`
+//! anywhere but at the end to anchor the pattern to the ignore file's own
+//! directory instead of matching a name at any depth below it.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::glob::Glob;
+
+/// The ignore files read in each directory, the one whose rules win first.
+const FILE_NAMES: [&str; 3] = [".minigrepignore", ".ignore", ".gitignore"];
+
+/// The ignore files that apply within a directory: its own, then those of the
+/// directories above it.
+#[derive(Debug, Default)]
+pub(crate) struct Ignores {
+    files: Vec<IgnoreFile>,
+    parent: Option<Arc<Ignores>>,
+}
+
+#[derive(Debug)]
+struct IgnoreFile {
+    /// The directory the rules are relative to, as a path from the walk.
+    dir: PathBuf,
+    /// Goes in front of paths below `dir`, for a file from above the walk's root.
+    prefix: PathBuf,
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    glob: Glob,
+    negated: bool,
+    dir_only: bool,
+    anchored: bool,
+}
+
+impl Ignores {
+    /// The ignore files for a walk starting at the directory `root` from the
+    /// directories above it, up to the top of the git repository it is in.
+    pub(crate) fn above(root: &Path) -> Arc<Ignores> {
+        let mut ignores = Arc::new(Ignores::default());
+        let Ok(absolute) = fs::canonicalize(root) else { return ignores };
+        if absolute.join(".git").exists() {
+            return ignores;
+        }
+
+        let ancestors: Vec<&Path> = absolute.ancestors().skip(1).collect();
+        let Some(top) = ancestors.iter().position(|dir| dir.join(".git").exists()) else { return ignores };
+        for dir in ancestors[..=top].iter().rev() {
+            let prefix = absolute.strip_prefix(dir).unwrap_or(&absolute).to_path_buf();
+            ignores = Ignores::read(ignores, dir, root, &prefix);
+        }
+        ignores
+    }
+
+    /// Adds the ignore files in `dir`, which the walk has just come to.
+    pub(crate) fn enter(self: &Arc<Ignores>, dir: &Path) -> Arc<Ignores> {
+        Ignores::read(Arc::clone(self), dir, dir, Path::new(""))
+    }
+
+    fn read(parent: Arc<Ignores>, from: &Path, dir: &Path, prefix: &Path) -> Arc<Ignores> {
+        let files: Vec<IgnoreFile> = FILE_NAMES
+            .iter()
+            .filter_map(|name| fs::read_to_string(from.join(name)).ok())
+            .map(|contents| IgnoreFile::parse(dir, prefix, &contents))
+            .collect();
+        if files.is_empty() {
+            return parent;
+        }
+        Arc::new(Ignores { files, parent: Some(parent) })
+    }
+
+    /// Whether `path`, found in the directory these are for, should be skipped.
+    pub(crate) fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let mut ignores = Some(self);
+        while let Some(current) = ignores {
+            for file in &current.files {
+                if let Some(ignored) = file.matched(path, is_dir) {
+                    return ignored;
+                }
+            }
+            ignores = current.parent.as_deref();
+        }
+        false
+    }
+}
+
+impl IgnoreFile {
+    fn parse(dir: &Path, prefix: &Path, contents: &str) -> IgnoreFile {
+        IgnoreFile {
+            dir: dir.to_path_buf(),
+            prefix: prefix.to_path_buf(),
+            rules: contents.lines().filter_map(Rule::parse).collect(),
+        }
+    }
+
+    /// `Some(true)` if the last rule to match `path` ignores it, `Some(false)` if
+    /// it includes it again, and `None` if no rule matches.
+    fn matched(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        let relative = self.prefix.join(path.strip_prefix(&self.dir).ok()?);
+        let components: Vec<_> = relative.components().map(|c| c.as_os_str().to_string_lossy()).collect();
+        let relative = components.join("/");
+        let name = components.last()?;
+
+        let rule = self.rules.iter().rev().find(|rule| {
+            (is_dir || !rule.dir_only) && rule.glob.is_match(if rule.anchored { &relative } else { name })
+        })?;
+        Some(!rule.negated)
+    }
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<Rule> {
+        if line.starts_with('#') {
+            return None;
+        }
+        // Trailing spaces don't count unless escaped.
+        let mut pattern = line.strip_suffix('\r').unwrap_or(line);
+        while pattern.ends_with(' ') && !pattern.ends_with("\\ ") {
+            pattern = &pattern[..pattern.len() - 1];
+        }
+
+        let (negated, pattern) = match pattern.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+        let (dir_only, pattern) = match pattern.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+        let anchored = pattern.contains('/');
+        let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+        if pattern.is_empty() {
+            return None;
+        }
+        Some(Rule { glob: Glob::new(pattern), negated, dir_only, anchored })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    fn ignored(contents: &str, path: &str, is_dir: bool) -> Option<bool> {
+        IgnoreFile::parse(Path::new("repo"), Path::new(""), contents).matched(&Path::new("repo").join(path), is_dir)
+    }
+
+    #[test]
+    fn gitignore_rules() {
+        let contents = "# build output\ntarget/\n*.log\n!keep.log\n/TODO\ndocs/*.html\n\\#hash\ntrailing  \n";
+        assert_eq!(ignored(contents, "target", true), Some(true));
+        assert_eq!(ignored(contents, "src/target", true), Some(true));
+        assert_eq!(ignored(contents, "target", false), None);
+        assert_eq!(ignored(contents, "a/b/debug.log", false), Some(true));
+        assert_eq!(ignored(contents, "a/keep.log", false), Some(false));
+        assert_eq!(ignored(contents, "TODO", false), Some(true));
+        assert_eq!(ignored(contents, "src/TODO", false), None);
+        assert_eq!(ignored(contents, "docs/index.html", false), Some(true));
+        assert_eq!(ignored(contents, "src/docs/index.html", false), None);
+        assert_eq!(ignored(contents, "#hash", false), Some(true));
+        assert_eq!(ignored(contents, "trailing", false), Some(true));
+        assert_eq!(ignored(contents, "# build output", false), None);
+    }
+
+    #[test]
+    fn deeper_files_win() {
+        let dir = TempDir::new("ignore", &[(".gitignore", "*.txt\n"), ("sub/.ignore", "!notes.txt\n")]);
+        let root = dir.path();
+
+        let top = Arc::new(Ignores::default()).enter(root);
+        let sub = top.enter(&root.join("sub"));
+        let result = [
+            top.is_ignored(&root.join("notes.txt"), false),
+            sub.is_ignored(&root.join("sub/notes.txt"), false),
+            sub.is_ignored(&root.join("sub/other.txt"), false),
+            sub.is_ignored(&root.join("sub/other.rs"), false),
+        ];
+
+        assert_eq!(result, [true, false, true, false]);
+    }
+}
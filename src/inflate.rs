@@ -0,0 +1,362 @@
+//! A streaming decoder for DEFLATE (RFC 1951), the compression inside gzip and zlib.
+
+use std::io::{self, Read};
+
+/// Furthest back a length/distance pair can reach.
+const WINDOW_SIZE: usize = 32 * 1024;
+
+const LENGTH_BASE: [u16; 29] =
+    [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] =
+    [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+/// The order the code lengths of the code length alphabet come in.
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid compressed data: {message}"))
+}
+
+fn ends_too_soon() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "compressed data ends too soon")
+}
+
+/// Reads a byte stream a few bits at a time, least significant bit first.
+pub(crate) struct BitReader<R> {
+    inner: R,
+    buf: Box<[u8; 8192]>,
+    pos: usize,
+    len: usize,
+    bits: u64,
+    count: u32,
+}
+
+impl<R: Read> BitReader<R> {
+    pub(crate) fn new(inner: R) -> BitReader<R> {
+        BitReader { inner, buf: Box::new([0; 8192]), pos: 0, len: 0, bits: 0, count: 0 }
+    }
+
+    fn next_byte(&mut self) -> io::Result<Option<u8>> {
+        if self.pos == self.len {
+            self.len = loop {
+                match self.inner.read(&mut self.buf[..]) {
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                    result => break result?,
+                }
+            };
+            self.pos = 0;
+            if self.len == 0 {
+                return Ok(None);
+            }
+        }
+        self.pos += 1;
+        Ok(Some(self.buf[self.pos - 1]))
+    }
+
+    /// Makes at least `n` bits available, or as many as are left at the end of the input.
+    fn fill(&mut self, n: u32) -> io::Result<()> {
+        while self.count < n {
+            match self.next_byte()? {
+                Some(byte) => {
+                    self.bits |= u64::from(byte) << self.count;
+                    self.count += 8;
+                }
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    fn bits(&mut self, n: u32) -> io::Result<u32> {
+        self.fill(n)?;
+        if self.count < n {
+            return Err(ends_too_soon());
+        }
+        let value = (self.bits & ((1 << n) - 1)) as u32;
+        self.consume(n);
+        Ok(value)
+    }
+
+    fn consume(&mut self, n: u32) {
+        self.bits >>= n;
+        self.count -= n;
+    }
+
+    /// Skips to the next whole byte and reads it, or returns `None` at the end of the input.
+    pub(crate) fn aligned_byte(&mut self) -> io::Result<Option<u8>> {
+        self.consume(self.count % 8);
+        if self.count > 0 {
+            return self.bits(8).map(|byte| Some(byte as u8));
+        }
+        self.next_byte()
+    }
+
+    /// Reads exactly `buf.len()` whole bytes.
+    pub(crate) fn aligned_bytes(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        for byte in buf {
+            *byte = self.aligned_byte()?.ok_or_else(ends_too_soon)?;
+        }
+        Ok(())
+    }
+}
+
+/// A canonical Huffman code, decoded through a table indexed by the next
+/// `max_len` bits of input.
+struct Huffman {
+    /// `(symbol, code length)`, with a length of 0 where no code fits.
+    table: Vec<(u16, u8)>,
+    max_len: u32,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> io::Result<Huffman> {
+        let max_len = u32::from(lengths.iter().copied().max().unwrap_or(0)).max(1);
+        let mut count = [0u16; 16];
+        for &len in lengths {
+            count[usize::from(len)] += 1;
+        }
+        count[0] = 0;
+
+        let mut next_code = [0u32; 16];
+        let mut code = 0;
+        for len in 1..16 {
+            code = (code + u32::from(count[len - 1])) << 1;
+            next_code[len] = code;
+        }
+
+        let mut table = vec![(0, 0); 1 << max_len];
+        for (symbol, &len) in lengths.iter().enumerate().filter(|(_, &len)| len > 0) {
+            let code = next_code[usize::from(len)];
+            next_code[usize::from(len)] += 1;
+            if code >= 1 << len {
+                return Err(invalid("oversubscribed Huffman code"));
+            }
+            // Codes are sent most significant bit first, into a stream read from the least.
+            let reversed = (code.reverse_bits() >> (32 - u32::from(len))) as usize;
+            for entry in table.iter_mut().skip(reversed).step_by(1 << len) {
+                *entry = (symbol as u16, len);
+            }
+        }
+        Ok(Huffman { table, max_len })
+    }
+
+    fn decode(&self, input: &mut BitReader<impl Read>) -> io::Result<u16> {
+        input.fill(self.max_len)?;
+        let index = (input.bits & ((1 << self.max_len) - 1)) as usize;
+        let (symbol, len) = self.table[index];
+        if len == 0 || u32::from(len) > input.count {
+            return Err(if input.count < self.max_len { ends_too_soon() } else { invalid("bad Huffman code") });
+        }
+        input.consume(u32::from(len));
+        Ok(symbol)
+    }
+}
+
+enum State {
+    BlockStart,
+    Stored { remaining: usize },
+    Compressed { literals: Huffman, distances: Huffman },
+    Finished,
+}
+
+/// Decompresses a DEFLATE stream as it is read.
+pub(crate) struct Inflate<R> {
+    input: BitReader<R>,
+    state: State,
+    last_block: bool,
+    /// Everything decoded lately: at least the last `WINDOW_SIZE` bytes, for copies
+    /// to reach back into, and whatever hasn't been read yet from `read_pos` on.
+    history: Vec<u8>,
+    read_pos: usize,
+}
+
+impl<R: Read> Inflate<R> {
+    pub(crate) fn new(input: BitReader<R>) -> Inflate<R> {
+        Inflate { input, state: State::BlockStart, last_block: false, history: Vec::new(), read_pos: 0 }
+    }
+
+    /// The input, for reading what follows the stream once `read` has returned 0.
+    pub(crate) fn input(&mut self) -> &mut BitReader<R> {
+        &mut self.input
+    }
+
+    /// Starts on another stream that follows in the same input.
+    pub(crate) fn restart(&mut self) {
+        self.state = State::BlockStart;
+        self.last_block = false;
+        self.history.clear();
+        self.read_pos = 0;
+    }
+
+    /// Decodes until there is a good amount of new output or the stream ends.
+    fn step(&mut self) -> io::Result<()> {
+        let target = self.history.len() + WINDOW_SIZE;
+        while self.history.len() < target {
+            match &mut self.state {
+                State::Finished => break,
+                State::BlockStart if self.last_block => self.state = State::Finished,
+                State::BlockStart => self.start_block()?,
+                State::Stored { remaining: 0 } => self.state = State::BlockStart,
+                State::Stored { remaining } => {
+                    let byte = self.input.aligned_byte()?.ok_or_else(ends_too_soon)?;
+                    *remaining -= 1;
+                    self.history.push(byte);
+                }
+                State::Compressed { literals, distances } => {
+                    let symbol = literals.decode(&mut self.input)?;
+                    match symbol {
+                        0..=255 => self.history.push(symbol as u8),
+                        256 => self.state = State::BlockStart,
+                        257..=285 => {
+                            let i = usize::from(symbol - 257);
+                            let len = usize::from(LENGTH_BASE[i])
+                                + self.input.bits(u32::from(LENGTH_EXTRA[i]))? as usize;
+                            let i = usize::from(distances.decode(&mut self.input)?);
+                            if i >= DISTANCE_BASE.len() {
+                                return Err(invalid("bad distance code"));
+                            }
+                            let distance = usize::from(DISTANCE_BASE[i])
+                                + self.input.bits(u32::from(DISTANCE_EXTRA[i]))? as usize;
+                            if distance > self.history.len() {
+                                return Err(invalid("distance too far back"));
+                            }
+                            // The source may overlap what is being written, so copy a byte at a time.
+                            let start = self.history.len() - distance;
+                            for j in 0..len {
+                                self.history.push(self.history[start + j]);
+                            }
+                        }
+                        _ => return Err(invalid("bad length code")),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn start_block(&mut self) -> io::Result<()> {
+        self.last_block = self.input.bits(1)? == 1;
+        self.state = match self.input.bits(2)? {
+            0 => {
+                let mut header = [0; 4];
+                self.input.aligned_bytes(&mut header)?;
+                let len = u16::from_le_bytes([header[0], header[1]]);
+                if len != !u16::from_le_bytes([header[2], header[3]]) {
+                    return Err(invalid("stored block length doesn't match its complement"));
+                }
+                State::Stored { remaining: usize::from(len) }
+            }
+            1 => {
+                let mut lengths = [0; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+                State::Compressed { literals: Huffman::new(&lengths)?, distances: Huffman::new(&[5; 30])? }
+            }
+            2 => self.dynamic_codes()?,
+            _ => return Err(invalid("reserved block type")),
+        };
+        Ok(())
+    }
+
+    fn dynamic_codes(&mut self) -> io::Result<State> {
+        let literal_count = self.input.bits(5)? as usize + 257;
+        let distance_count = self.input.bits(5)? as usize + 1;
+        let code_length_count = self.input.bits(4)? as usize + 4;
+
+        let mut code_lengths = [0; 19];
+        for &i in &CODE_LENGTH_ORDER[..code_length_count] {
+            code_lengths[i] = self.input.bits(3)? as u8;
+        }
+        let code_lengths = Huffman::new(&code_lengths)?;
+
+        let mut lengths = Vec::with_capacity(literal_count + distance_count);
+        while lengths.len() < literal_count + distance_count {
+            let (len, repeat) = match code_lengths.decode(&mut self.input)? {
+                len @ 0..=15 => (len as u8, 1),
+                16 => {
+                    let previous = *lengths.last().ok_or_else(|| invalid("repeat with nothing before it"))?;
+                    (previous, 3 + self.input.bits(2)?)
+                }
+                17 => (0, 3 + self.input.bits(3)?),
+                _ => (0, 11 + self.input.bits(7)?),
+            };
+            lengths.extend(std::iter::repeat_n(len, repeat as usize));
+        }
+        if lengths.len() > literal_count + distance_count || lengths[256] == 0 {
+            return Err(invalid("bad code lengths"));
+        }
+
+        let (literals, distances) = lengths.split_at(literal_count);
+        Ok(State::Compressed { literals: Huffman::new(literals)?, distances: Huffman::new(distances)? })
+    }
+}
+
+impl<R: Read> Read for Inflate<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.read_pos == self.history.len() {
+            // Keep only what copies can still reach back to.
+            if self.history.len() > 2 * WINDOW_SIZE {
+                self.history.drain(..self.history.len() - WINDOW_SIZE);
+                self.read_pos = self.history.len();
+            }
+            self.step()?;
+        }
+        let n = buf.len().min(self.history.len() - self.read_pos);
+        buf[..n].copy_from_slice(&self.history[self.read_pos..self.read_pos + n]);
+        self.read_pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inflate(data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        Inflate::new(BitReader::new(data)).read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn block_types() {
+        // Stored: one final block holding "frog".
+        assert_eq!(inflate(&[0x01, 0x04, 0x00, 0xfb, 0xff, b'f', b'r', b'o', b'g']).unwrap(), b"frog");
+        // Fixed codes, from zlib.
+        let fixed = [0x4b, 0x2b, 0xca, 0x4f, 0x57, 0x48, 0x43, 0x21, 0xb8, 0x00];
+        assert_eq!(inflate(&fixed).unwrap(), b"frog frog frog frog\n");
+        // Dynamic codes, from zlib with only Huffman coding.
+        let dynamic = [
+            0x05, 0xc1, 0x31, 0x01, 0x00, 0x00, 0x0c, 0xc3, 0xa0, 0x7f, 0x2e, 0x49, 0xfd, 0x7b, 0x18, 0x68, 0x83, 0x91,
+            0x01, 0x6d, 0xa2, 0x09, 0x14, 0xf7,
+        ];
+        assert_eq!(inflate(&dynamic).unwrap(), b"abccaaaacaabacaaaaaabccabaabcabaaaaabbaa\n");
+    }
+
+    #[test]
+    fn long_output() {
+        // "frog\n" 40000 times, which is mostly copies far longer than the window.
+        let mut data = vec![
+            0xed, 0xc4, 0xa1, 0x0d, 0x00, 0x00, 0x08, 0x03, 0x30, 0xcf, 0xa1, 0x43, 0x92, 0xf0, 0xbf, 0xe0, 0x06, 0x7c,
+            0x2b, 0x9a, 0x9d, 0xae, 0x48, 0x92,
+        ];
+        for _ in 0..96 {
+            data.extend_from_slice(&[0x24, 0x49, 0x92]);
+        }
+        data.extend_from_slice(&[0xa4, 0x4f, 0x07]);
+        assert_eq!(inflate(&data).unwrap(), b"frog\n".repeat(40000));
+    }
+
+    #[test]
+    fn bad_input() {
+        assert!(inflate(&[0x07]).is_err());
+        assert!(inflate(&[0x01, 0x04, 0x00, 0x00, 0x00]).is_err());
+        assert_eq!(inflate(&[0x01, 0x04, 0x00, 0xfb, 0xff, b'f']).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
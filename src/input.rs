@@ -0,0 +1,65 @@
+//! Reading the text to be searched without holding all of it in memory.
+
+use std::io::{self, BufRead};
+
+/// Splits a reader into blocks of whole lines.
+///
+/// Each block is whatever the reader has buffered, extended to the end of the
+/// line it stops in, so memory use is bounded by the reader's buffer plus the
+/// longest line while a pipe still gets its lines searched as soon as they
+/// arrive.
+pub struct LineBlocks<R> {
+    reader: R,
+    block: Vec<u8>,
+}
+
+impl<R: BufRead> LineBlocks<R> {
+    pub fn new(reader: R) -> LineBlocks<R> {
+        LineBlocks { reader, block: Vec::new() }
+    }
+
+    /// Reads the next block, or returns `None` at the end of the input. The bytes
+    /// are passed on as they are; it is up to the caller to decide what they hold.
+    pub fn next_block(&mut self) -> io::Result<Option<&[u8]>> {
+        self.block.clear();
+
+        let available = self.reader.fill_buf()?;
+        if available.is_empty() {
+            return Ok(None);
+        }
+        match available.iter().rposition(|&b| b == b'\n') {
+            Some(end) => {
+                self.block.extend_from_slice(&available[..=end]);
+                self.reader.consume(end + 1);
+            }
+            None => {
+                let len = available.len();
+                self.block.extend_from_slice(available);
+                self.reader.consume(len);
+                self.reader.read_until(b'\n', &mut self.block)?;
+            }
+        }
+
+        Ok(Some(&self.block))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_end_on_line_boundaries() {
+        let text = "one\ntwo\nthree\nfour";
+        let reader = io::BufReader::with_capacity(6, text.as_bytes());
+        let mut blocks = LineBlocks::new(reader);
+
+        let mut seen = Vec::new();
+        while let Some(block) = blocks.next_block().unwrap() {
+            assert!(block.ends_with(b"\n") || block == b"four");
+            seen.push(String::from_utf8(block.to_vec()).unwrap());
+        }
+        assert_eq!(seen.concat(), text);
+        assert!(seen.len() > 1);
+    }
+}
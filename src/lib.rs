@@ -1,11 +1,249 @@
+use std::borrow::Cow;
 use std::error::Error;
-use std::{env, fs};
+use std::io::{BufRead, BufReader, BufWriter, IsTerminal, Read, Write};
+use std::num::NonZeroUsize;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::{env, fs, io, iter, thread};
 
-#[derive(Debug, PartialEq)]
+mod aho_corasick;
+mod args;
+mod casefold;
+mod decompress;
+pub mod encoding;
+mod glob;
+mod ignore;
+mod inflate;
+mod input;
+pub mod matcher;
+mod parallel;
+mod printer;
+pub mod regex;
+mod substring;
+#[cfg(test)]
+mod temp_dir;
+mod walk;
+
+pub use args::{ArgsError, USAGE};
+pub use glob::Glob;
+pub use matcher::Matcher;
+
+use encoding::{DecodeReader, Encoding, RawOffsets};
+use input::LineBlocks;
+use printer::Printer;
+use regex::Regex;
+use walk::Walk;
+
+#[derive(Debug, Default, PartialEq)]
 pub struct Config {
-    pub query: String,
-    pub file_path: String,
+    /// What to search for; a line matches if any of them does.
+    pub patterns: Vec<String>,
+    /// The files to search, in order. `-` stands for standard input, and with
+    /// `recursive` an empty path for the current directory, left out of the
+    /// names of the files found in it.
+    pub paths: Vec<PathBuf>,
     pub ignore_case: bool,
+    /// `-S`: ignore case only if no pattern has an uppercase letter. `Config::build`
+    /// works this out into `ignore_case` once the patterns are known.
+    pub smart_case: bool,
+    /// The compiled patterns when searching with `--regex` / `-E`.
+    pub regex: Option<Regex>,
+    /// Whether each of `paths` is walked as a directory tree (`-r`).
+    pub recursive: bool,
+    /// Walk into everything, ignore files and `.git` included (`--no-ignore`).
+    pub no_ignore: bool,
+    /// Only files matching one of these are searched, if there are any (`--include`).
+    pub include: Vec<Glob>,
+    /// Files matching any of these are skipped (`--exclude`).
+    pub exclude: Vec<Glob>,
+    /// Directories matching any of these aren't walked into (`--exclude-dir`).
+    pub exclude_dir: Vec<Glob>,
+    /// What to do with files that don't look like text (`--binary-files`).
+    pub binary_files: BinaryFiles,
+    /// What the files are encoded in (`--encoding`).
+    pub encoding: Encoding,
+    /// Decompress gzip and zlib files to search what is inside them (`-z`).
+    pub search_zip: bool,
+    /// Prefix each line with its 1-based line number (`-n`).
+    pub line_number: bool,
+    /// Prefix each line with the 0-based byte offset of its start (`-b`).
+    pub byte_offset: bool,
+    /// Number of lines to print after each match (`-A`, or `-C`), if any were asked for.
+    /// Even none puts `--` between groups of lines that aren't next to each other.
+    pub after_context: Option<usize>,
+    /// Number of lines to print before each match (`-B`, or `-C`), likewise.
+    pub before_context: Option<usize>,
+    /// Only match whole words (`-w`).
+    pub word_regexp: bool,
+    /// Only match whole lines (`-x`).
+    pub line_regexp: bool,
+    /// Select the lines that don't match instead (`-v`).
+    pub invert_match: bool,
+    /// What to print for each file.
+    pub output: OutputMode,
+    /// Print each occurrence on its own line instead of whole lines (`-o`).
+    pub only_matching: bool,
+    /// When to highlight matches and prefixes with ANSI colors (`--color`).
+    pub color: ColorChoice,
+    /// How many files to search at once (`-j`), or 0 for one per CPU.
+    pub threads: usize,
+    /// The order files are printed in when searched at once (`--sort`).
+    pub sort: SortBy,
+}
+
+/// The values of `--color`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    /// Color only when writing to a terminal and NO_COLOR isn't set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Decides whether output written to stdout should be colored.
+    pub fn use_color(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+            }
+        }
+    }
+}
+
+/// The values of `--binary-files`, for files with a NUL byte in them.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum BinaryFiles {
+    /// Search them, but only say whether they matched instead of printing lines.
+    #[default]
+    Binary,
+    /// Search them like any other file (`-a`).
+    Text,
+    /// Take them not to match (`-I`).
+    WithoutMatch,
+}
+
+/// The values of `--sort`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum SortBy {
+    /// Each file as soon as it has been searched, though paths given on the
+    /// command line are still printed in the order they were given.
+    #[default]
+    None,
+    /// In the order the paths were given, and by name within a directory, as if
+    /// the files had been searched one at a time.
+    Path,
+}
+
+/// What `run` prints for each file it searches.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum OutputMode {
+    /// The selected lines themselves.
+    #[default]
+    Lines,
+    /// How many lines were selected (`-c`).
+    Count,
+    /// Just the name of the file, if any line was selected (`-l`).
+    FilesWithMatches,
+    /// Just the name of the file, if no line was selected (`-L`).
+    FilesWithoutMatch,
+    /// A JSON object on a line of its own for the start and end of each file and
+    /// for each selected or context line, then one for the whole search (`--json`).
+    Json,
+}
+
+/// A line that matched, along with where it was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match<'a> {
+    /// The 1-based number of the line.
+    pub line_number: usize,
+    /// The byte offset of the start of the line.
+    pub byte_offset: usize,
+    /// The line itself, without its line terminator.
+    pub line: &'a str,
+    /// Byte ranges within `line` of every non-overlapping occurrence, left to right.
+    pub spans: Vec<Range<usize>>,
+}
+
+/// What a call to `run` found, so `main` can pick an exit status.
+#[derive(Debug, Default, PartialEq)]
+pub struct Summary {
+    /// Number of lines printed as matches.
+    pub matched_lines: usize,
+    /// Number of files or directories that could not be read.
+    pub errors: usize,
+    /// Number of files that were searched to the end, or far enough to tell.
+    pub files_searched: usize,
+    /// Number of those with a line selected.
+    pub files_matched: usize,
+    /// Whether files were listed for having no line selected (`-L`), so that
+    /// listing one is what counts as success.
+    pub without_match: bool,
+}
+
+impl Summary {
+    /// True when something matched, or with `-L` when some file didn't, and every
+    /// file could be read.
+    pub fn success(&self) -> bool {
+        let found = if self.without_match { self.files_searched > self.files_matched } else { self.matched_lines > 0 };
+        found && self.errors == 0
+    }
+
+    /// Adds up how searching one file went. A file that can't be read only earns a
+    /// warning, the rest are still searched; output that can't be written is an error
+    /// for the whole run.
+    fn record(&mut self, result: Result<usize, SearchError>) -> io::Result<()> {
+        match result {
+            Ok(matched_lines) => {
+                self.matched_lines += matched_lines;
+                self.files_searched += 1;
+                self.files_matched += usize::from(matched_lines > 0);
+            }
+            Err(SearchError::Read(err)) => {
+                eprintln!("minigrep: {err}");
+                self.errors += 1;
+            }
+            Err(SearchError::Write(err)) => return Err(err),
+        }
+        Ok(())
+    }
+
+    /// Handles flushing the output at the end, which fails the same way writing it does.
+    fn record_flush(&mut self, result: io::Result<()>) -> io::Result<()> {
+        match result {
+            Ok(()) => Ok(()),
+            Err(err) => self.record(Err(SearchError::Write(err))),
+        }
+    }
+}
+
+/// Why searching one file stopped short.
+#[derive(Debug)]
+enum SearchError {
+    /// The file couldn't be opened or read.
+    Read(io::Error),
+    /// The results couldn't be written out.
+    Write(io::Error),
+}
+
+impl SearchError {
+    /// Names the file a read error happened in.
+    fn with_path(self, path: &Path) -> SearchError {
+        match self {
+            SearchError::Read(err) => SearchError::Read(walk::with_path(err, path)),
+            write => write,
+        }
+    }
+}
+
+/// For `?` on what the printer returns; reads are mapped to `SearchError::Read` by hand.
+impl From<io::Error> for SearchError {
+    fn from(err: io::Error) -> SearchError {
+        SearchError::Write(err)
+    }
 }
 
 impl Config {
@@ -15,73 +253,395 @@ impl Config {
     /// 1   irrelevant, since if you pass in env::args().iter(), the first arg will be the program's name,
     ///     which we don't need at all
     /// 
-    /// 2   the substring you want to query
+    /// 2   the substring you want to query, unless patterns were given with `-e` or `-f`
     /// 
-    /// 3   the path to file
+    /// 3.. the paths to the files, standard input is searched when there are none (the current
+    ///     directory with `-r`)
     /// 
-    /// 4.. irrelevant
+    /// Options such as `-i` or `--regex` may appear anywhere before a `--`, see `args::USAGE`
+    /// for the full list. `ignore_case` starts out from the IGNORE_CASE env var and is then
+    /// overridden by whichever of `-i`, `-S` and `--no-ignore-case` comes last.
     /// 
-    /// if the iter does not contains at least 3 elements, this function will failed by returning a Err
+    /// if there is no pattern, an option is unknown, a pattern file can't be read or a regex doesn't compile, this function will
+    /// failed by returning a Err, which is also how `--help` and `--version` are reported
     /// 
     /// # Examples
     /// 
     /// ```
     /// let iter = vec![String::from(""),String::from("so"),String::from("poem.txt")].into_iter();
     /// let c = minigrep::Config {
-    ///     query: String::from("so"),
-    ///     file_path: String::from("poem.txt"),
+    ///     patterns: vec![String::from("so")],
+    ///     paths: vec![std::path::PathBuf::from("poem.txt")],
     ///     ignore_case: std::env::var("IGNORE_CASE").is_ok(),
+    ///     smart_case: false,
+    ///     regex: None,
+    ///     recursive: false,
+    ///     no_ignore: false,
+    ///     include: vec![],
+    ///     exclude: vec![],
+    ///     exclude_dir: vec![],
+    ///     binary_files: minigrep::BinaryFiles::Binary,
+    ///     encoding: minigrep::encoding::Encoding::Auto,
+    ///     search_zip: false,
+    ///     line_number: false,
+    ///     byte_offset: false,
+    ///     after_context: None,
+    ///     before_context: None,
+    ///     word_regexp: false,
+    ///     line_regexp: false,
+    ///     invert_match: false,
+    ///     output: minigrep::OutputMode::Lines,
+    ///     only_matching: false,
+    ///     color: minigrep::ColorChoice::Auto,
+    ///     threads: 0,
+    ///     sort: minigrep::SortBy::None,
     /// };
     /// assert_eq!(minigrep::Config::build(iter), Ok(c));
     /// 
     /// ```
-    pub fn build(mut args: impl Iterator<Item = String>) -> Result<Config, &'static str> {
-        args.next();
-        let query = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a query string"),
-        };
-        
-        let file_path = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a file path"),
-        };
-
-        let ignore_case = env::var("IGNORE_CASE").is_ok();
+    pub fn build(args: impl Iterator<Item = String>) -> Result<Config, ArgsError> {
+        args::parse(args)
+    }
 
-        Ok(Config { query, file_path, ignore_case })
+    /// Whether `run` searches the file at `path` as far as `include` and `exclude`
+    /// are concerned. Standard input is always searched.
+    pub fn selects(&self, path: &Path) -> bool {
+        path.as_os_str() == STDIN_PATH
+            || ((self.include.is_empty() || self.include.iter().any(|glob| glob.is_match_path(path)))
+                && !self.exclude.iter().any(|glob| glob.is_match_path(path)))
     }
 }
 
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    let contents = fs::read_to_string(config.file_path)?;
+pub fn run(config: Config) -> Result<Summary, Box<dyn Error>> {
+    let matcher = Matcher::new(&config);
+    let color = config.color.use_color();
+    let mut summary = Summary { without_match: config.output == OutputMode::FilesWithoutMatch, ..Summary::default() };
+    let with_filename = config.recursive || config.paths.len() > 1;
 
-    if !config.ignore_case {
-        for line in search(&config.query, &contents) {
-            println!("{line}");
+    let files = config.paths.iter().flat_map(|path| -> Box<dyn Iterator<Item = io::Result<PathBuf>> + Send> {
+        if config.recursive && path.as_os_str() == CURRENT_DIR_PATH {
+            let walk = Walk::new(".").ignore_files(!config.no_ignore).exclude_dirs(config.exclude_dir.clone());
+            Box::new(walk.map(|file| file.map(|path| path.strip_prefix(".").map(Path::to_path_buf).unwrap_or(path))))
+        } else if config.recursive && path.as_os_str() != STDIN_PATH {
+            Box::new(Walk::new(path).ignore_files(!config.no_ignore).exclude_dirs(config.exclude_dir.clone()))
+        } else {
+            Box::new(iter::once(Ok(path.clone())))
         }
+    });
+    let files = files.filter(|file| file.as_ref().map_or(true, |path| config.selects(path)));
+
+    let threads = match config.threads {
+        0 => thread::available_parallelism().map_or(1, NonZeroUsize::get),
+        threads => threads,
+    };
+    if threads == 1 || !with_filename {
+        let mut printer = Printer::new(&config, buffer_stdout(io::stdout().lock()), color);
+        for file in files {
+            let result = file.map_err(SearchError::Read);
+            summary.record(result.and_then(|path| search_path(&matcher, &mut printer, &path, with_filename)))?;
+        }
+        // What is still buffered can fail to be written like the rest, broken pipe and all.
+        summary.record_flush(printer.into_inner().flush())?;
     } else {
-        for line in search_case_intensive(&config.query, &contents) {
-            println!("{line}");
+        let mut out = search_in_parallel(&config, &matcher, files, threads, buffer_stdout(io::stdout()), color, &mut summary)?;
+        summary.record_flush(out.flush())?;
+    }
+
+    if config.output == OutputMode::Json {
+        Printer::new(&config, io::stdout().lock(), color).summary(&summary)?;
+    }
+    Ok(summary)
+}
+
+/// Searches `files` on `threads` threads, printing each file's output to `out`
+/// whole, and in the order the files come in unless they are all found under
+/// one path and `--sort` isn't given.
+fn search_in_parallel<W: Write + Send>(
+    config: &Config,
+    matcher: &Matcher,
+    files: impl Iterator<Item = io::Result<PathBuf>> + Send,
+    threads: usize,
+    out: W,
+    color: bool,
+    summary: &mut Summary,
+) -> io::Result<W> {
+    let ordered = config.sort == SortBy::Path || config.paths.len() > 1;
+    let output = parallel::Output::new(out, ordered, Printer::new(config, Vec::new(), color).group_separator());
+    let search_file = |index, file: io::Result<PathBuf>| {
+        // The file at the head of the output is written as it is searched, the
+        // others are held until it is their turn.
+        let mut printer = Printer::new(config, output.section(index), color);
+        let result = file.map_err(SearchError::Read);
+        let result = result.and_then(|path| search_path(matcher, &mut printer, &path, true));
+        printer.into_inner().finish().map_err(SearchError::Write).and(result)
+    };
+    parallel::for_each(files, threads, search_file, |result| summary.record(result))?;
+    Ok(output.into_inner())
+}
+
+/// Size of the buffer standard output is written through when it isn't a terminal.
+const WRITE_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Buffers `out`, which is standard output, unless that is a terminal, where matches
+/// should show up as they are found: with no room in the buffer, every write goes
+/// straight to `Stdout`, which sends out whole lines.
+fn buffer_stdout<W: Write>(out: W) -> BufWriter<W> {
+    let capacity = if io::stdout().is_terminal() { 0 } else { WRITE_BUFFER_SIZE };
+    BufWriter::with_capacity(capacity, out)
+}
+
+/// The path that makes `run` read standard input instead of a file.
+pub(crate) const STDIN_PATH: &str = "-";
+
+/// The path that makes `run` walk the current directory without naming it, for `-r`
+/// with no paths given.
+pub(crate) const CURRENT_DIR_PATH: &str = "";
+
+/// Size of the buffer files are read through, which bounds how much of a file is held at once.
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Prints the results for one file, or for standard input, returning how many lines were selected.
+fn search_path(
+    matcher: &Matcher,
+    printer: &mut Printer<impl Write>,
+    path: &Path,
+    with_filename: bool,
+) -> Result<usize, SearchError> {
+    if path.as_os_str() == STDIN_PATH {
+        let name = "(standard input)";
+        let reader = open_reader(printer.config(), io::stdin().lock());
+        let (reader, raw_offsets) = reader.map_err(|err| SearchError::Read(walk::with_path(err, Path::new(name))))?;
+        printer.begin(name, with_filename)?;
+        printer.set_raw_offsets(raw_offsets);
+        search_reader(matcher, printer, reader).map_err(|err| err.with_path(Path::new(name)))
+    } else {
+        let reader = fs::File::open(path).and_then(|file| open_reader(printer.config(), file));
+        let (reader, raw_offsets) = reader.map_err(|err| SearchError::Read(walk::with_path(err, path)))?;
+        printer.begin(&path.display().to_string(), with_filename)?;
+        printer.set_raw_offsets(raw_offsets);
+        search_reader(matcher, printer, reader).map_err(|err| err.with_path(path))
+    }
+}
+
+/// Puts together what turns the raw bytes of an input into the text to search, along
+/// with where that text is in the input, after any decompression.
+fn open_reader<'a>(config: &Config, input: impl Read + 'a) -> io::Result<(impl BufRead + 'a, RawOffsets)> {
+    let input: Box<dyn Read + 'a> =
+        if config.search_zip { decompress::decompress(BufReader::new(input))? } else { Box::new(input) };
+    let decoder = DecodeReader::new(input, config.encoding);
+    let raw_offsets = decoder.raw_offsets();
+    Ok((BufReader::with_capacity(READ_BUFFER_SIZE, decoder), raw_offsets))
+}
+
+/// Searches `reader` a block of lines at a time, printing matches as soon as they are found.
+///
+/// Bytes that aren't UTF-8 are searched as U+FFFD. Once a block turns out to be binary,
+/// the rest of the input is taken to be too, and with `--binary-files=binary` its first
+/// matching line is reported with a note instead of being printed.
+fn search_reader(
+    matcher: &Matcher,
+    printer: &mut Printer<impl Write>,
+    reader: impl BufRead,
+) -> Result<usize, SearchError> {
+    let binary_files = printer.config().binary_files;
+    let mut blocks = LineBlocks::new(reader);
+    let mut binary = false;
+    let mut matched_lines = 0;
+    let mut lines_before = 0;
+    let mut bytes_before = 0;
+
+    while let Some(bytes) = blocks.next_block().map_err(SearchError::Read)? {
+        if !binary && is_binary(bytes) {
+            binary = true;
+            if binary_files == BinaryFiles::WithoutMatch {
+                break;
+            }
+        }
+        let block = String::from_utf8_lossy(bytes);
+        if let Cow::Owned(_) = block {
+            printer.set_replacements(Replacements::new(bytes_before, bytes));
+        } else {
+            printer.set_replacements(Replacements { start: bytes_before, ends: Vec::new() });
+        }
+        let block = &*block;
+
+        match printer.config().output {
+            OutputMode::Lines | OutputMode::Json if binary && binary_files == BinaryFiles::Binary => {
+                if search_lines(matcher, block).next().is_some() {
+                    printer.binary_matched()?;
+                    matched_lines += 1;
+                    break;
+                }
+                continue;
+            }
+            OutputMode::Lines | OutputMode::Json => {}
+            OutputMode::Count => {
+                matched_lines += search_lines(matcher, block).count();
+                continue;
+            }
+            // Whether a file is listed is settled by its first match, so stop reading there.
+            OutputMode::FilesWithMatches | OutputMode::FilesWithoutMatch => {
+                if search_lines(matcher, block).next().is_some() {
+                    matched_lines = 1;
+                    break;
+                }
+                continue;
+            }
+        }
+
+        let mut matches = search(matcher, block).into_iter().peekable();
+        matched_lines += matches.len();
+
+        if printer.wants_context() {
+            for mut line in lines(block) {
+                line.line_number += lines_before;
+                line.byte_offset += bytes_before;
+                match matches.next_if(|m| m.line_number + lines_before == line.line_number) {
+                    Some(matched) => printer.matched(&Match { spans: matched.spans, ..line })?,
+                    None => printer.context(&line)?,
+                }
+            }
+        } else {
+            for mut matched in matches {
+                matched.line_number += lines_before;
+                matched.byte_offset += bytes_before;
+                printer.matched(&matched)?;
+            }
+        }
+        lines_before += substring::count(b'\n', bytes);
+        bytes_before += bytes.len();
+    }
+
+    printer.end(matched_lines)?;
+    Ok(matched_lines)
+}
+
+/// Where `String::from_utf8_lossy` put a 3-byte U+FFFD in place of bytes that
+/// weren't UTF-8, so that offsets in the decoded text can be taken back to where
+/// they are in the text `DecodeReader` read.
+#[derive(Debug, Default)]
+pub(crate) struct Replacements {
+    /// Offset of the block that was decoded.
+    start: usize,
+    /// For each replacement, where it ends in the decoded block and where the bytes it
+    /// replaced end in the raw block.
+    ends: Vec<(usize, usize)>,
+}
+
+impl Replacements {
+    pub(crate) fn new(start: usize, bytes: &[u8]) -> Replacements {
+        let mut ends = Vec::new();
+        let (mut decoded, mut raw) = (0, 0);
+        for chunk in bytes.utf8_chunks() {
+            decoded += chunk.valid().len();
+            raw += chunk.valid().len();
+            if !chunk.invalid().is_empty() {
+                decoded += char::REPLACEMENT_CHARACTER.len_utf8();
+                raw += chunk.invalid().len();
+                ends.push((decoded, raw));
+            }
+        }
+        Replacements { start, ends }
+    }
+
+    /// Takes an offset into the input, counting the decoded block in place of the raw
+    /// one, to the offset it stands for. Offsets before the block are left alone.
+    pub(crate) fn raw_offset(&self, offset: usize) -> usize {
+        let Some(within) = offset.checked_sub(self.start) else { return offset };
+        match self.ends.partition_point(|&(decoded, _)| decoded <= within) {
+            0 => offset,
+            i => {
+                let (decoded, raw) = self.ends[i - 1];
+                self.start + raw + (within - decoded)
+            }
         }
     }
+}
 
-    Ok(())
+/// Whether `bytes` look like they come from a binary file rather than text, which
+/// almost never has a NUL in it. Text that is merely not UTF-8 is still searched.
+fn is_binary(bytes: &[u8]) -> bool {
+    substring::memchr(0, bytes).is_some()
 }
 
+/// Finds the lines of `contents` that `matcher` matches, or the ones it doesn't if it is
+/// inverted, in which case `spans` is always empty.
+///
+/// # Examples
+///
+/// ```
+/// let matcher = minigrep::Matcher::literal("body", true);
+/// let matches = minigrep::search(&matcher, "I'm nobody!\nWho are you?\nAre you nobody, too?");
+///
+/// assert_eq!(matches.len(), 2);
+/// assert_eq!(matches[1].line_number, 3);
+/// assert_eq!(matches[1].byte_offset, 25);
+/// assert_eq!(matches[1].line, "Are you nobody, too?");
+/// assert_eq!(matches[1].spans, vec![10..14]);
+/// ```
+pub fn search<'a>(matcher: &Matcher, contents: &'a str) -> Vec<Match<'a>> {
+    search_lines(matcher, contents).collect()
+}
 
-fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    contents.lines().filter(|line| line.contains(query)).collect()
+/// The lazy version of `search`, for callers that may not need every match.
+fn search_lines<'a: 'm, 'm>(matcher: &'m Matcher, contents: &'a str) -> Box<dyn Iterator<Item = Match<'a>> + 'm> {
+    match matcher.is_literal() && !matcher.is_inverted() {
+        true => Box::new(search_hits(matcher, contents)),
+        false => Box::new(lines(contents).filter_map(|mut m| {
+            m.spans = matcher.find_iter(m.line).collect();
+            (m.spans.is_empty() == matcher.is_inverted()).then_some(m)
+        })),
+    }
+}
+
+/// Looks for `matcher`'s literal through the whole of `contents`, and only works out
+/// where lines begin and end around each hit.
+fn search_hits<'a: 'm, 'm>(matcher: &'m Matcher, contents: &'a str) -> impl Iterator<Item = Match<'a>> + 'm {
+    let bytes = contents.as_bytes();
+    // Always the start of a line.
+    let mut pos = 0;
+    let mut line_number = 1;
+    let mut counted = 0;
+
+    iter::from_fn(move || loop {
+        let hit = matcher.find_literal_at(contents, pos)?.start;
+        let start = bytes[pos..hit].iter().rposition(|&b| b == b'\n').map_or(pos, |i| pos + i + 1);
+        let end = substring::memchr(b'\n', &bytes[hit..]).map_or(bytes.len(), |i| hit + i);
+        pos = (end + 1).min(bytes.len());
+
+        line_number += substring::count(b'\n', &bytes[counted..start]);
+        counted = start;
+
+        let line = &contents[start..end];
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        // The hit may still be rejected, by `-w` say, or a later occurrence accepted instead.
+        let spans: Vec<_> = matcher.find_iter(line).collect();
+        if !spans.is_empty() {
+            return Some(Match { line_number, byte_offset: start, line, spans });
+        }
+    })
 }
 
-fn search_case_intensive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    let query = query.to_lowercase();
-    contents.lines().filter(|line| line.to_lowercase().contains(&query)).collect()
+/// Like `str::lines`, but keeps track of where each line is.
+pub(crate) fn lines(contents: &str) -> impl Iterator<Item = Match<'_>> {
+    let mut byte_offset = 0;
+    contents.split_inclusive('\n').enumerate().map(move |(i, line)| {
+        let start = byte_offset;
+        byte_offset += line.len();
+        let line = line.strip_suffix('\n').unwrap_or(line);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        Match { line_number: i + 1, byte_offset: start, line, spans: Vec::new() }
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use temp_dir::TempDir;
+
+    fn text<'a>(matches: Vec<Match<'a>>) -> Vec<&'a str> {
+        matches.into_iter().map(|m| m.line).collect()
+    }
 
     #[test]
     fn case_sensitive() {
@@ -95,9 +655,9 @@ safe, fast, productive.
 Pick three.
 Shit I'm sick now.";
 
-        assert_eq!(vec!["safe, fast, productive."], search(query, contents));
-        assert_eq!(vec!["Pick three.", "Shit I'm sick now."], search(query2, contents));
-        assert_eq!(empty_vec, search(query3, contents));
+        assert_eq!(vec!["safe, fast, productive."], text(search(&Matcher::literal(query, false), contents)));
+        assert_eq!(vec!["Pick three.", "Shit I'm sick now."], text(search(&Matcher::literal(query2, false), contents)));
+        assert_eq!(empty_vec, text(search(&Matcher::literal(query3, false), contents)));
     }
 
     #[test]
@@ -109,6 +669,268 @@ safe, fast, productive.
 Pick three.
 Trust me.";
 
-        assert_eq!(vec!["Rust:", "Trust me."], search_case_intensive(query, contents));
+        assert_eq!(vec!["Rust:", "Trust me."], text(search(&Matcher::literal(query, true), contents)));
+    }
+
+    #[test]
+    fn regex() {
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Trust me.";
+
+        let regex = Regex::new("^(Rust|Pick)|me\\.$", false).unwrap();
+        assert_eq!(vec!["Rust:", "Pick three.", "Trust me."], text(search(&Matcher::regex(regex), contents)));
+    }
+
+    #[test]
+    fn whole_word() {
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Trust me.";
+
+        let config = Config { patterns: vec!["rUSt".to_string()], ignore_case: true, word_regexp: true, ..Config::default() };
+        assert_eq!(vec!["Rust:"], text(search(&Matcher::new(&config), contents)));
+    }
+
+    #[test]
+    fn invert() {
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Trust me.";
+
+        let matcher = Matcher::literal("rust", true).inverted();
+        assert_eq!(vec!["safe, fast, productive.", "Pick three."], text(search(&matcher, contents)));
+
+        let matcher = Matcher::regex(Regex::new("^[A-Z]", false).unwrap()).inverted();
+        assert_eq!(vec!["safe, fast, productive."], text(search(&matcher, contents)));
+    }
+
+    #[test]
+    fn positions() {
+        let contents = "Rust:\r\nsafe, fast, productive.\n\nTrust me, just this once.";
+        let matches = search(&Matcher::literal("st", false), contents);
+
+        assert_eq!(
+            matches.iter().map(|m| (m.line_number, m.byte_offset, m.line)).collect::<Vec<_>>(),
+            vec![(1, 0, "Rust:"), (2, 7, "safe, fast, productive."), (4, 32, "Trust me, just this once.")]
+        );
+        assert_eq!(matches[2].spans, vec![3..5, 12..14]);
+    }
+
+    #[test]
+    fn whole_buffer_hits() {
+        let contents = "frog\r\nbog\nfrogs, frog\nfroggy\n\nfrog";
+        let matches = search(&Matcher::literal("frog", false), contents);
+        let lines: Vec<_> = matches.iter().map(|m| (m.line_number, m.byte_offset, m.line, m.spans.len())).collect();
+        assert_eq!(lines, vec![(1, 0, "frog", 1), (3, 10, "frogs, frog", 2), (4, 22, "froggy", 1), (6, 30, "frog", 1)]);
+
+        let config = Config { patterns: vec!["frog".to_string()], word_regexp: true, ..Config::default() };
+        let matches = search(&Matcher::new(&config), contents);
+        assert_eq!(matches.iter().map(|m| m.line_number).collect::<Vec<_>>(), vec![1, 3, 6]);
+        assert_eq!(matches[1].spans, vec![7..11]);
+    }
+
+    #[test]
+    fn include_and_exclude() {
+        let config = Config { include: vec![Glob::new("*.{rs,toml}")], exclude: vec![Glob::new("build.rs")], ..Config::default() };
+        assert!(config.selects(Path::new("src/main.rs")));
+        assert!(config.selects(Path::new("./Cargo.toml")));
+        assert!(!config.selects(Path::new("README.md")));
+        assert!(!config.selects(Path::new("build.rs")));
+        assert!(config.selects(Path::new("-")));
+    }
+
+    #[test]
+    fn binary_files() {
+        let search_bytes = |binary_files, contents: &[u8]| {
+            let config = Config { patterns: vec!["frog".to_string()], binary_files, ..Config::default() };
+            let mut printer = Printer::new(&config, Vec::new(), false);
+            printer.begin("data.bin", false).unwrap();
+            let matched = search_reader(&Matcher::new(&config), &mut printer, contents).unwrap();
+            (matched, String::from_utf8(printer.into_inner()).unwrap())
+        };
+        let contents = b"a frog\n\0\xff frog\nfrog again\n";
+
+        assert_eq!(search_bytes(BinaryFiles::Binary, contents), (1, "Binary file data.bin matches\n".to_string()));
+        assert_eq!(search_bytes(BinaryFiles::WithoutMatch, contents), (0, String::new()));
+        assert_eq!(
+            search_bytes(BinaryFiles::Text, contents),
+            (3, "a frog\n\0\u{fffd} frog\nfrog again\n".to_string())
+        );
+        assert_eq!(search_bytes(BinaryFiles::Binary, b"\0 toad\n"), (0, String::new()));
+        assert_eq!(search_bytes(BinaryFiles::Binary, b"\xfffrog\n"), (1, "\u{fffd}frog\n".to_string()));
+    }
+
+    #[test]
+    fn offsets_after_invalid_utf8() {
+        let search_bytes = |config: Config, contents: &[u8]| {
+            let config = Config { patterns: vec!["frog".to_string()], byte_offset: true, ..config };
+            let mut printer = Printer::new(&config, Vec::new(), false);
+            printer.begin("data.txt", false).unwrap();
+            search_reader(&Matcher::new(&config), &mut printer, contents).unwrap();
+            String::from_utf8(printer.into_inner()).unwrap()
+        };
+        let contents = b"ab\xffcd frog\n\xff\xfe x\nfrog\n";
+
+        let only_matching = Config { only_matching: true, ..Config::default() };
+        assert_eq!(search_bytes(only_matching, contents), "6:frog\n16:frog\n");
+        let context = Config { before_context: Some(1), ..Config::default() };
+        assert_eq!(search_bytes(context, contents), "0:ab\u{fffd}cd frog\n11-\u{fffd}\u{fffd} x\n16:frog\n");
+        let json = search_bytes(Config { output: OutputMode::Json, ..Config::default() }, b"\xe2\x82 frog frog\n");
+        assert!(json.contains(r#""submatches":[{"text":"frog","start":3,"end":7},{"text":"frog","start":8,"end":12}]"#));
+
+        let replacements = Replacements::new(100, b"a\xffb\xe2\x82c");
+        let raw: Vec<usize> = [99, 100, 101, 104, 105, 108].map(|offset| replacements.raw_offset(offset)).to_vec();
+        assert_eq!(raw, [99, 100, 101, 102, 103, 105]);
+    }
+
+    #[test]
+    fn offsets_in_other_encodings() {
+        let search_bytes = |encoding: Encoding, contents: &[u8]| {
+            let config = Config { patterns: vec!["frog".to_string()], byte_offset: true, encoding, ..Config::default() };
+            let mut printer = Printer::new(&config, Vec::new(), false);
+            printer.begin("data.txt", false).unwrap();
+            let (reader, raw_offsets) = open_reader(&config, contents).unwrap();
+            printer.set_raw_offsets(raw_offsets);
+            search_reader(&Matcher::new(&config), &mut printer, reader).unwrap();
+            String::from_utf8(printer.into_inner()).unwrap()
+        };
+        let utf16 = |bom: &[u8], text: &str, to_bytes: fn(u16) -> [u8; 2]| -> Vec<u8> {
+            bom.iter().copied().chain(text.encode_utf16().flat_map(to_bytes)).collect()
+        };
+
+        assert_eq!(search_bytes(Encoding::Latin1, b"caf\xe9 caf\xe9\nfrog\n"), "10:frog\n");
+        assert_eq!(search_bytes(Encoding::Windows1252, b"\x93hi\x94 \x80\nfrog\n"), "7:frog\n");
+        assert_eq!(search_bytes(Encoding::Auto, b"\xef\xbb\xbffrog\n"), "3:frog\n");
+        assert_eq!(search_bytes(Encoding::Utf8, b"\xef\xbb\xbf\xffa\nfrog\n"), "6:frog\n");
+        let le = utf16(&[0xff, 0xfe], "ab\nfrog\n", u16::to_le_bytes);
+        assert_eq!(search_bytes(Encoding::Auto, &le), "8:frog\n");
+        let be = utf16(&[], "caf\u{e9} \u{1f438}\nfrog frog\n", u16::to_be_bytes);
+        assert_eq!(search_bytes(Encoding::Utf16Be, &be), "16:frog frog\n");
+
+        // Offsets within the line, too.
+        let config = Config { output: OutputMode::Json, encoding: Encoding::Utf16Be, ..Config::default() };
+        let config = Config { patterns: vec!["frog".to_string()], ..config };
+        let mut printer = Printer::new(&config, Vec::new(), false);
+        let (reader, raw_offsets) = open_reader(&config, &be[..]).unwrap();
+        printer.set_raw_offsets(raw_offsets);
+        search_reader(&Matcher::new(&config), &mut printer, reader).unwrap();
+        let json = String::from_utf8(printer.into_inner()).unwrap();
+        assert!(json.contains(r#""byte_offset":16,"#));
+        assert!(json.contains(r#""submatches":[{"text":"frog","start":0,"end":8},{"text":"frog","start":10,"end":18}]"#));
+    }
+
+    #[test]
+    fn read_and_write_errors() {
+        struct Failing;
+        impl Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::from(io::ErrorKind::InvalidData))
+            }
+        }
+        impl Write for Failing {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::from(io::ErrorKind::BrokenPipe))
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let config = Config { patterns: vec!["frog".to_string()], ..Config::default() };
+        let matcher = Matcher::new(&config);
+        let mut summary = Summary::default();
+
+        let mut printer = Printer::new(&config, Vec::new(), false);
+        let read = search_reader(&matcher, &mut printer, BufReader::new(Failing));
+        assert!(matches!(read, Err(SearchError::Read(_))));
+        assert!(summary.record(read).is_ok());
+
+        let mut printer = Printer::new(&config, Failing, false);
+        let write = search_reader(&matcher, &mut printer, &b"a frog\n"[..]);
+        assert!(matches!(write, Err(SearchError::Write(_))));
+        assert_eq!(summary.record(write).unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+        assert_eq!(summary.errors, 1);
+    }
+
+    #[test]
+    fn success() {
+        let config = Config { patterns: vec!["frog".to_string()], ..Config::default() };
+        let matcher = Matcher::new(&config);
+        let search = |summary: &mut Summary, contents: &[u8]| {
+            let mut printer = Printer::new(&config, Vec::new(), false);
+            summary.record(search_reader(&matcher, &mut printer, contents)).unwrap();
+        };
+
+        let mut summary = Summary::default();
+        search(&mut summary, b"a toad\n");
+        assert!(!summary.success());
+        search(&mut summary, b"a frog\n");
+        assert!(summary.success());
+
+        // With -L, a file without a match is what is looked for.
+        let mut summary = Summary { without_match: true, ..Summary::default() };
+        search(&mut summary, b"a frog\n");
+        assert!(!summary.success());
+        search(&mut summary, b"a toad\n");
+        assert!(summary.success());
+        summary.errors += 1;
+        assert!(!summary.success());
+    }
+
+    #[test]
+    fn files_in_order() {
+        // The first file takes the longest, so the others are done before it.
+        let big = "frog\n".repeat(100_000);
+        let files = [("big.txt", &*big), ("a.txt", "a frog\n"), ("none.txt", "a toad\n"), ("b.txt", "frog\nb\n")];
+        let dir = TempDir::new("order", &files);
+        let mut paths: Vec<PathBuf> = files.iter().map(|(name, _)| dir.path().join(name)).collect();
+        paths.push(dir.path().join("missing.txt"));
+
+        let config = Config {
+            patterns: vec!["frog".to_string()],
+            paths: paths.clone(),
+            after_context: Some(1),
+            ..Config::default()
+        };
+        let files = paths.iter().cloned().map(Ok);
+        let mut summary = Summary::default();
+        let out = search_in_parallel(&config, &Matcher::new(&config), files, 4, Vec::new(), false, &mut summary).unwrap();
+
+        let big = format!("{}:frog\n", paths[0].display()).repeat(100_000);
+        let (a, b) = (paths[1].display(), paths[3].display());
+        assert_eq!(String::from_utf8(out).unwrap(), format!("{big}--\n{a}:a frog\n--\n{b}:frog\n{b}-b\n"));
+        assert_eq!((summary.files_searched, summary.matched_lines, summary.errors), (4, 100_002, 1));
+    }
+
+    #[test]
+    fn build_collects_paths() {
+        let args = ["minigrep", "so", "poem.txt", "-r", "src"].map(String::from).into_iter();
+        let config = Config::build(args).unwrap();
+        assert_eq!(config.paths, vec![PathBuf::from("poem.txt"), PathBuf::from("src")]);
+        assert!(config.recursive);
+
+        let args = ["minigrep", "so"].map(String::from).into_iter();
+        assert_eq!(Config::build(args).unwrap().paths, vec![PathBuf::from("-")]);
+
+        let args = ["minigrep"].map(String::from).into_iter();
+        assert!(Config::build(args).is_err());
+    }
+
+    #[test]
+    fn build_compiles_regex() {
+        let args = ["minigrep", "-E", "fr[o", "poem.txt"].map(String::from).into_iter();
+        assert!(Config::build(args).is_err());
+
+        let args = ["minigrep", "fr[o]g", "poem.txt", "--regex"].map(String::from).into_iter();
+        let config = Config::build(args).unwrap();
+        assert_eq!(config.regex.unwrap().patterns(), ["fr[o]g"]);
+        assert_eq!(config.paths, vec![PathBuf::from("poem.txt")]);
     }
 }
\ No newline at end of file
@@ -1,15 +1,29 @@
-use std::{env, process};
+use std::{env, io, process};
 
-use minigrep::Config;
+use minigrep::{ArgsError, Config, USAGE};
 
 fn main() {
-    let config = Config::build(env::args().into_iter()).unwrap_or_else(|err| {
-        eprintln!("Problem parsing arguments: {err}");
-        process::exit(1);
+    let config = Config::build(env::args()).unwrap_or_else(|err| {
+        match err {
+            ArgsError::Help => print!("{USAGE}"),
+            ArgsError::Version => println!("minigrep {}", env!("CARGO_PKG_VERSION")),
+            err => {
+                eprintln!("Problem parsing arguments: {err}");
+                eprintln!("Try 'minigrep --help' for more information.");
+                process::exit(1);
+            }
+        }
+        process::exit(0);
     });
 
-    if let Err(e) = minigrep::run(config) {
-        eprintln!("Application error: {e}");
-        process::exit(1);
+    match minigrep::run(config) {
+        Ok(summary) if summary.success() => {}
+        Ok(_) => process::exit(1),
+        // Whatever was reading the output has had all it wanted, as with `| head`.
+        Err(e) if e.downcast_ref::<io::Error>().is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe) => {}
+        Err(e) => {
+            eprintln!("Application error: {e}");
+            process::exit(1);
+        }
     }
 }
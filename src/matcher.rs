@@ -0,0 +1,320 @@
+//! Finding where a query occurs within a single line.
+
+use std::ops::Range;
+
+use crate::aho_corasick::AhoCorasick;
+use crate::casefold;
+use crate::regex::{self, Regex};
+use crate::substring::{self, Finder};
+use crate::Config;
+
+/// Decides which lines match, and where, for the patterns in a `Config`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matcher {
+    kind: Kind,
+    invert: bool,
+    /// Boundaries a literal occurrence must respect; a regex has them compiled in.
+    whole_word: bool,
+    whole_line: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+    Literal(Finder),
+    /// The query is stored case folded, along with the index of the byte in it
+    /// `candidate_ignore_case` looks for, if it has one.
+    IgnoreCase { query: String, rare: Option<usize> },
+    /// Several literals at once.
+    Many(AhoCorasick),
+    Regex(Regex),
+}
+
+impl Matcher {
+    /// Builds the matcher `run` would use for `config`.
+    pub fn new(config: &Config) -> Matcher {
+        let matcher = match &config.regex {
+            Some(regex) => {
+                let flags = regex::Flags {
+                    whole_word: config.word_regexp,
+                    whole_line: config.line_regexp,
+                    ..regex.flags()
+                };
+                Matcher::regex(regex.recompile(flags))
+            }
+            None => Matcher {
+                whole_word: config.word_regexp,
+                whole_line: config.line_regexp,
+                ..Matcher::literals(&config.patterns, config.ignore_case)
+            },
+        };
+        if config.invert_match { matcher.inverted() } else { matcher }
+    }
+
+    /// Matches `query` as a plain substring.
+    pub fn literal(query: &str, ignore_case: bool) -> Matcher {
+        let kind = if ignore_case {
+            let query: String = query.chars().flat_map(casefold::fold).collect();
+            Kind::IgnoreCase { rare: rare_ignore_case(&query), query }
+        } else {
+            Kind::Literal(Finder::new(query.as_bytes()))
+        };
+        Matcher { kind, invert: false, whole_word: false, whole_line: false }
+    }
+
+    /// Matches wherever any of `patterns` occurs as a plain substring.
+    pub fn literals(patterns: &[String], ignore_case: bool) -> Matcher {
+        match patterns {
+            [pattern] => Matcher::literal(pattern, ignore_case),
+            _ => {
+                let kind = Kind::Many(AhoCorasick::new(patterns, ignore_case));
+                Matcher { kind, invert: false, whole_word: false, whole_line: false }
+            }
+        }
+    }
+
+    /// Matches a compiled regular expression.
+    pub fn regex(regex: Regex) -> Matcher {
+        Matcher { kind: Kind::Regex(regex), invert: false, whole_word: false, whole_line: false }
+    }
+
+    /// Whether this looks for non-empty literals, which `find_literal_at` can find
+    /// in a whole buffer at once instead of line by line.
+    pub(crate) fn is_literal(&self) -> bool {
+        match &self.kind {
+            Kind::Literal(finder) => !finder.needle().is_empty(),
+            Kind::IgnoreCase { query, .. } => !query.is_empty(),
+            Kind::Many(patterns) => !patterns.has_empty(),
+            Kind::Regex(_) => false,
+        }
+    }
+
+    /// Makes `search` select the lines this matcher does *not* match.
+    pub fn inverted(self) -> Matcher {
+        Matcher { invert: !self.invert, ..self }
+    }
+
+    /// Whether `search` selects the lines that don't match.
+    pub fn is_inverted(&self) -> bool {
+        self.invert
+    }
+
+    /// Returns true if the query occurs anywhere in `line`, regardless of inversion.
+    pub fn is_match(&self, line: &str) -> bool {
+        self.find_at(line, 0).is_some()
+    }
+
+    /// Finds the first occurrence in `line` starting at or after byte `start`.
+    pub fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        match &self.kind {
+            Kind::Regex(regex) => return regex.find_at(line, start).map(|(begin, end)| begin..end),
+            Kind::Many(patterns) => return patterns.find_at(line, start, |found| self.within_boundaries(line, found)),
+            Kind::Literal(_) | Kind::IgnoreCase { .. } => {}
+        }
+
+        // Occurrences can overlap, so a rejected one only moves the search on by a character.
+        let mut from = start;
+        loop {
+            let found = self.find_literal_at(line, from)?;
+            if self.within_boundaries(line, &found) {
+                return Some(found);
+            }
+            from = found.start + line[found.start..].chars().next()?.len_utf8();
+        }
+    }
+
+    /// Finds the next occurrence of a literal, ignoring `-w` and `-x`.
+    pub(crate) fn find_literal_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        match &self.kind {
+            Kind::Literal(finder) => {
+                let len = finder.needle().len();
+                finder.find(&line.as_bytes()[start..]).map(|i| start + i..start + i + len)
+            }
+            Kind::IgnoreCase { query, rare } => {
+                let mut from = start;
+                loop {
+                    let begin = from + candidate_ignore_case(&line.as_bytes()[from..], query, *rare)?;
+                    if let Some(len) = prefix_len_ignore_case(&line[begin..], query) {
+                        return Some(begin..begin + len);
+                    }
+                    from = begin + line[begin..].chars().next()?.len_utf8();
+                }
+            }
+            Kind::Many(patterns) => patterns.find_at(line, start, |_| true),
+            Kind::Regex(_) => unreachable!("matched without looking for candidates"),
+        }
+    }
+
+    fn within_boundaries(&self, line: &str, found: &Range<usize>) -> bool {
+        if self.whole_line && *found != (0..line.len()) {
+            return false;
+        }
+        if self.whole_word {
+            let before = line[..found.start].chars().next_back();
+            let after = line[found.end..].chars().next();
+            return !before.is_some_and(regex::is_word_char) && !after.is_some_and(regex::is_word_char);
+        }
+        true
+    }
+
+    /// Every non-overlapping occurrence in `line`, from left to right.
+    pub fn find_iter<'a>(&'a self, line: &'a str) -> impl Iterator<Item = Range<usize>> + 'a {
+        let mut start = 0;
+        std::iter::from_fn(move || {
+            if start > line.len() {
+                return None;
+            }
+            let found = self.find_at(line, start)?;
+            // Step past empty matches so the search always moves forward.
+            start = if found.is_empty() {
+                found.end + line[found.end..].chars().next().map_or(1, char::len_utf8)
+            } else {
+                found.end
+            };
+            Some(found)
+        })
+    }
+}
+
+/// Picks the byte of `folded_query` least likely to show up in text, in either
+/// case, out of those before its first character that isn't ASCII.
+fn rare_ignore_case(folded_query: &str) -> Option<usize> {
+    let ascii = folded_query.bytes().take_while(u8::is_ascii).enumerate();
+    let frequency = |byte: u8| substring::frequency(byte) + substring::frequency(byte.to_ascii_uppercase());
+    ascii.min_by_key(|&(_, byte)| frequency(byte)).map(|(i, _)| i)
+}
+
+/// Skips to the first place in `text` where an occurrence of `folded_query` could
+/// start. ASCII characters only fold to themselves or their other case, so while
+/// the text is ASCII the byte at `rare` is that far into any occurrence and only it
+/// needs looking for; text that isn't ASCII is looked through for the query's first
+/// character instead, without folding anything that can't be the start of it.
+fn candidate_ignore_case(text: &[u8], folded_query: &str, rare: Option<usize>) -> Option<usize> {
+    if let Some(rare) = rare {
+        let byte = folded_query.as_bytes()[rare];
+        let found = text.get(rare..).map(|rest| substring::memchr2_or_non_ascii(byte, byte.to_ascii_uppercase(), rest));
+        if text.iter().take(rare).all(u8::is_ascii) {
+            match found {
+                Some(None) => return None,
+                Some(Some(i)) if text[rare + i].is_ascii() => return Some(i),
+                _ => {}
+            }
+        }
+    }
+    match folded_query.bytes().next() {
+        None => Some(0),
+        Some(first) if first.is_ascii() => {
+            substring::memchr2_or_non_ascii(first, first.to_ascii_uppercase(), text)
+        }
+        Some(_) => text.iter().position(|b| !b.is_ascii()),
+    }
+}
+
+/// If `text` starts with `folded_query` once case folded, returns how many bytes
+/// of `text` that took. Every character of `text` has to fold to a whole part of
+/// the query, so "s" doesn't match the start of "ß".
+fn prefix_len_ignore_case(text: &str, folded_query: &str) -> Option<usize> {
+    let mut expected = folded_query.chars();
+
+    for (i, c) in text.char_indices() {
+        if expected.as_str().is_empty() {
+            return Some(i);
+        }
+        if c.is_ascii() {
+            if expected.next() != Some(c.to_ascii_lowercase()) {
+                return None;
+            }
+            continue;
+        }
+        for folded in casefold::fold(c) {
+            if expected.next() != Some(folded) {
+                return None;
+            }
+        }
+    }
+    expected.as_str().is_empty().then_some(text.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_every_occurrence() {
+        let matcher = Matcher::literal("ru", false);
+        assert_eq!(matcher.find_iter("Rust, trust, truth").collect::<Vec<_>>(), vec![7..9, 14..16]);
+
+        let matcher = Matcher::literal("RU", true);
+        assert_eq!(matcher.find_iter("Rust, trust").collect::<Vec<_>>(), vec![0..2, 7..9]);
+
+        let matcher = Matcher::regex(Regex::new("a*", false).unwrap());
+        assert_eq!(matcher.find_iter("baa").collect::<Vec<_>>(), vec![0..0, 1..3, 3..3]);
+    }
+
+    #[test]
+    fn whole_words_and_lines() {
+        let config = Config { patterns: vec!["rust".to_string()], ignore_case: true, word_regexp: true, ..Config::default() };
+        let matcher = Matcher::new(&config);
+        assert_eq!(matcher.find_iter("Trust rust, RUST_2 Rust").collect::<Vec<_>>(), vec![6..10, 19..23]);
+
+        let config = Config { patterns: vec!["aa".to_string()], word_regexp: true, ..Config::default() };
+        assert_eq!(Matcher::new(&config).find_at("aaa aa", 0), Some(4..6));
+
+        let config = Config { patterns: vec!["Rust:".to_string()], line_regexp: true, ..Config::default() };
+        assert!(Matcher::new(&config).is_match("Rust:"));
+        assert!(!Matcher::new(&config).is_match("Rust: trust"));
+
+        let regex = Regex::new("t?rust", true).unwrap();
+        let config = Config { regex: Some(regex), word_regexp: true, ..Config::default() };
+        assert_eq!(Matcher::new(&config).find_iter("rusty TRUST").collect::<Vec<_>>(), vec![6..11]);
+    }
+
+    #[test]
+    fn many_literals() {
+        let patterns = ["frog".to_string(), "bog".to_string(), "body".to_string()];
+        let matcher = Matcher::literals(&patterns, true);
+        assert_eq!(matcher.find_iter("Somebody? A FROG in a bog").collect::<Vec<_>>(), vec![4..8, 12..16, 22..25]);
+
+        let config = Config { patterns: patterns.to_vec(), word_regexp: true, ..Config::default() };
+        assert_eq!(Matcher::new(&config).find_iter("frogs in the bog").collect::<Vec<_>>(), vec![13..16]);
+        // Searched through the whole text at once, and then line by line where there is a hit.
+        let matches = crate::search(&Matcher::new(&config), "frogs\nboggy FROG\nbodyfrog\nthe bog body\n");
+        let lines: Vec<_> = matches.iter().map(|m| (m.line_number, m.spans.clone())).collect();
+        assert_eq!(lines, vec![(4, vec![4..7, 8..12])]);
+
+        assert!(!Matcher::literals(&[], false).is_match("anything"));
+    }
+
+    #[test]
+    fn ignore_case_spans_follow_the_line() {
+        // 'İ' folds to two characters, so offsets differ from the folded line.
+        let matcher = Matcher::literal("stanbul", true);
+        assert_eq!(matcher.find_at("İSTANBUL", 0), Some(2..9));
+
+        let matcher = Matcher::literal("STRASSE", true);
+        assert_eq!(matcher.find_iter("Straße, strasse, STRAẞE").collect::<Vec<_>>(), vec![0..7, 9..16, 18..26]);
+
+        let matcher = Matcher::literal("ﬁsh", true);
+        assert_eq!(matcher.find_iter("FISH ﬁsh fiſh").collect::<Vec<_>>(), vec![0..4, 5..10, 11..16]);
+
+        assert_eq!(Matcher::literal("ss", true).find_at("Maß", 0), Some(2..4));
+        assert_eq!(Matcher::literal("s", true).find_at("Maß", 0), None);
+        assert!(!Matcher::literal("i", true).is_match("ı"));
+        assert!(Matcher::literal("İ", true).is_match("i\u{307}"));
+    }
+
+    #[test]
+    fn ignore_case_looks_for_a_rare_byte() {
+        assert_eq!(rare_ignore_case("sherlock holmes"), Some(7));
+        assert_eq!(rare_ignore_case("über"), None);
+        assert_eq!(rare_ignore_case(""), None);
+
+        let matcher = Matcher::literal("Sherlock Holmes", true);
+        assert_eq!(matcher.find_iter("sherlock, SHERLOCK HOLMES and sherlock holmes").collect::<Vec<_>>(), vec![10..25, 30..45]);
+        // Characters that aren't ASCII before the rare byte, which fold to ASCII.
+        let matcher = Matcher::literal("kelvin", true);
+        assert_eq!(matcher.find_iter("\u{212a}elvin ſ KELVIN").collect::<Vec<_>>(), vec![0..8, 12..18]);
+        let matcher = Matcher::literal("ssk", true);
+        assert_eq!(matcher.find_at("ſsk", 0), Some(0..4));
+        assert_eq!(matcher.find_at("sk", 0), None);
+    }
+}
@@ -0,0 +1,295 @@
+//! Searching many files at once on a pool of threads.
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+/// How much a `Section` gathers before it tries to write it out.
+const FLUSH_SIZE: usize = 8 * 1024;
+
+/// Calls `work` on each of `jobs` on `threads` worker threads, along with the
+/// job's index, and hands every result to `emit` back on the calling thread as
+/// soon as it is ready.
+///
+/// Jobs are queued from a thread of their own, so a slow directory walk doesn't
+/// hold up workers or output. The first error `emit` returns stops the rest of
+/// the jobs from being started, and is returned.
+pub(crate) fn for_each<J, R, E>(
+    jobs: impl Iterator<Item = J> + Send,
+    threads: usize,
+    work: impl Fn(usize, J) -> R + Sync,
+    mut emit: impl FnMut(R) -> Result<(), E>,
+) -> Result<(), E>
+where
+    J: Send,
+    R: Send,
+{
+    let (job_tx, job_rx) = mpsc::sync_channel(threads * 2);
+    let (result_tx, result_rx) = mpsc::channel();
+    let job_rx = Mutex::new(job_rx);
+    let stop = AtomicBool::new(false);
+
+    thread::scope(|scope| {
+        let stop = &stop;
+        scope.spawn(move || {
+            for job in jobs.enumerate() {
+                if stop.load(Ordering::Relaxed) || job_tx.send(job).is_err() {
+                    break;
+                }
+            }
+        });
+        for _ in 0..threads {
+            let (job_rx, result_tx, work) = (&job_rx, result_tx.clone(), &work);
+            scope.spawn(move || loop {
+                // Only held while waiting for a job, not while working on it.
+                let job = job_rx.lock().unwrap().recv();
+                let Ok((i, job)) = job else { break };
+                // Once stopped, jobs still queued are taken but not done, so the
+                // queue can't fill up and leave the producer stuck.
+                if !stop.load(Ordering::Relaxed) {
+                    let _ = result_tx.send(work(i, job));
+                }
+            });
+        }
+        drop(result_tx);
+
+        for result in result_rx {
+            if let Err(err) = emit(result) {
+                stop.store(true, Ordering::Relaxed);
+                return Err(err);
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Output shared by the jobs of `for_each`, each writing to a `Section` of its own
+/// so what they write isn't interleaved.
+///
+/// Only one section at a time, the one at the head, goes straight to `out`; the
+/// rest are held until it is their turn. With `ordered`, sections are written in
+/// the order of their indexes and the head is the lowest one not finished yet,
+/// otherwise whichever section writes first is the head until it finishes.
+pub(crate) struct Output<W> {
+    state: Mutex<State<W>>,
+    ordered: bool,
+    /// Written between sections that both have something in them.
+    separator: Vec<u8>,
+}
+
+struct State<W> {
+    out: W,
+    /// The head when `ordered`.
+    next: usize,
+    /// The head when not `ordered`, if any section has claimed it.
+    owner: Option<usize>,
+    /// Sections that finished before it was their turn.
+    done: BTreeMap<usize, Vec<u8>>,
+    wrote_any: bool,
+}
+
+impl<W: Write> Output<W> {
+    pub(crate) fn new(out: W, ordered: bool, separator: Vec<u8>) -> Output<W> {
+        let state = State { out, next: 0, owner: None, done: BTreeMap::new(), wrote_any: false };
+        Output { state: Mutex::new(state), ordered, separator }
+    }
+
+    /// The section for the job with index `index`, which must be finished for the
+    /// sections after it to be written when `ordered`.
+    pub(crate) fn section(&self, index: usize) -> Section<'_, W> {
+        Section { output: self, index, buffer: Vec::new(), started: false }
+    }
+
+    /// Gives back `out`, once every section has finished.
+    pub(crate) fn into_inner(self) -> W {
+        self.state.into_inner().unwrap().out
+    }
+}
+
+impl<W: Write> State<W> {
+    fn is_head(&self, index: usize, ordered: bool) -> bool {
+        if ordered {
+            self.next == index
+        } else {
+            self.owner.is_none_or(|owner| owner == index)
+        }
+    }
+
+    /// Writes part of a section, which is the first part of it if not `started`.
+    fn write(&mut self, bytes: &[u8], started: bool, separator: &[u8]) -> io::Result<()> {
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        if !started && self.wrote_any {
+            self.out.write_all(separator)?;
+        }
+        self.wrote_any = true;
+        self.out.write_all(bytes)
+    }
+}
+
+/// Where one job writes its part of an `Output`.
+pub(crate) struct Section<'a, W> {
+    output: &'a Output<W>,
+    index: usize,
+    buffer: Vec<u8>,
+    /// Whether some of this section has been written out already.
+    started: bool,
+}
+
+impl<W: Write> Section<'_, W> {
+    /// Writes out what has been gathered if this section is at the head.
+    fn write_if_head(&mut self) -> io::Result<()> {
+        let output = self.output;
+        let mut state = output.state.lock().unwrap();
+        if !state.is_head(self.index, output.ordered) {
+            return Ok(());
+        }
+        if !output.ordered {
+            state.owner = Some(self.index);
+        }
+        state.write(&self.buffer, self.started, &output.separator)?;
+        self.started |= !self.buffer.is_empty();
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// Ends the section, writing out what is left of it, and any sections after it
+    /// that were only waiting for this one, if it is at the head.
+    pub(crate) fn finish(mut self) -> io::Result<()> {
+        let output = self.output;
+        let mut guard = output.state.lock().unwrap();
+        let state = &mut *guard;
+        if !state.is_head(self.index, output.ordered) {
+            state.done.insert(self.index, mem::take(&mut self.buffer));
+            return Ok(());
+        }
+        state.write(&self.buffer, self.started, &output.separator)?;
+
+        if output.ordered {
+            state.next += 1;
+            while let Some(buffer) = state.done.remove(&state.next) {
+                state.write(&buffer, false, &output.separator)?;
+                state.next += 1;
+            }
+        } else {
+            state.owner = None;
+            for buffer in mem::take(&mut state.done).into_values() {
+                state.write(&buffer, false, &output.separator)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for Section<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= FLUSH_SIZE {
+            self.write_if_head()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_if_head()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// Lets a test look at what has been written while jobs are still running.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn every_job_is_done() {
+        let mut results = Vec::new();
+        let emit = |i| {
+            results.push(i);
+            Ok::<_, ()>(())
+        };
+        for_each(10..18, 3, |i, job| (i, job), emit).unwrap();
+        results.sort();
+        assert_eq!(results, (0..8).zip(10..18).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn stops_at_an_error() {
+        let done = AtomicUsize::new(0);
+        let work = |_, i: usize| {
+            done.fetch_add(1, Ordering::Relaxed);
+            i
+        };
+        let result = for_each(0..100_000, 4, work, |i| if i == 10 { Err(i) } else { Ok(()) });
+        assert_eq!(result, Err(10));
+        assert!(done.load(Ordering::Relaxed) < 1000);
+    }
+
+    #[test]
+    fn sections_in_order() {
+        let out = Shared::default();
+        let output = Output::new(out.clone(), true, b"--\n".to_vec());
+        let big = "x".repeat(FLUSH_SIZE) + "\n";
+        let work = |i, _| {
+            let mut section = output.section(i);
+            match i {
+                // The first section is written as it goes, while the rest wait.
+                0 => {
+                    thread::sleep(Duration::from_millis(50));
+                    section.write_all(big.as_bytes()).unwrap();
+                    assert_eq!(out.0.lock().unwrap().len(), big.len());
+                    thread::sleep(Duration::from_millis(50));
+                    section.write_all(b"a\n").unwrap();
+                }
+                2 => {}
+                _ => writeln!(section, "{i}").unwrap(),
+            }
+            section.finish()
+        };
+        for_each(0..5, 4, work, |result| result).unwrap();
+        assert_eq!(String::from_utf8(out.0.lock().unwrap().clone()).unwrap(), big + "a\n--\n1\n--\n3\n--\n4\n");
+    }
+
+    #[test]
+    fn sections_unordered() {
+        let out = Shared::default();
+        let output = Output::new(out.clone(), false, Vec::new());
+        let work = |i: usize, _| {
+            let mut section = output.section(i);
+            // Later jobs finish first.
+            thread::sleep(Duration::from_millis(5 * (8 - i as u64)));
+            writeln!(section, "{i}a").unwrap();
+            thread::sleep(Duration::from_millis(5));
+            writeln!(section, "{i}b").unwrap();
+            section.finish()
+        };
+        for_each(0..8, 4, work, |result| result).unwrap();
+
+        let written = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = written.lines().collect();
+        assert_eq!(lines.len(), 16);
+        // Each section is whole, whatever order they come in.
+        for pair in lines.chunks(2) {
+            assert_eq!(pair[0].replace('a', "b"), pair[1]);
+        }
+    }
+}
@@ -0,0 +1,443 @@
+//! Formatting matched lines, and the context around them, for `run`.
+
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::slice;
+
+use crate::encoding::RawOffsets;
+use crate::{Config, Match, OutputMode, Replacements, Summary};
+
+// The colors GNU grep uses by default.
+const MATCH_COLOR: &str = "\x1b[1;31m";
+const NAME_COLOR: &str = "\x1b[35m";
+const NUMBER_COLOR: &str = "\x1b[32m";
+const SEPARATOR_COLOR: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+/// A line kept around in case it turns out to be before-context.
+struct Buffered {
+    line_number: usize,
+    byte_offset: usize,
+    line: String,
+}
+
+/// Writes the lines of each input in turn, adding the context the config asks
+/// for and a `--` between groups of lines that aren't contiguous.
+///
+/// With `OutputMode::Json` each line is an object instead, with a `type` of
+/// `begin`, `match`, `context`, `binary`, `end` or `summary`.
+pub(crate) struct Printer<'c, W> {
+    config: &'c Config,
+    out: W,
+    color: bool,
+    name: String,
+    show_name: bool,
+    before: VecDeque<Buffered>,
+    after_remaining: usize,
+    last_printed: Option<usize>,
+    printed_any: bool,
+    /// For the block of text being searched, to give byte offsets as they are in the input.
+    replacements: Replacements,
+    /// For the whole input, after `replacements`.
+    raw_offsets: RawOffsets,
+}
+
+impl<'c, W: Write> Printer<'c, W> {
+    pub(crate) fn new(config: &'c Config, out: W, color: bool) -> Printer<'c, W> {
+        Printer {
+            config,
+            out,
+            color,
+            name: String::new(),
+            show_name: false,
+            before: VecDeque::new(),
+            after_remaining: 0,
+            last_printed: None,
+            printed_any: false,
+            replacements: Replacements::default(),
+            raw_offsets: RawOffsets::default(),
+        }
+    }
+
+    pub(crate) fn config(&self) -> &'c Config {
+        self.config
+    }
+
+    pub(crate) fn into_inner(self) -> W {
+        self.out
+    }
+
+    /// Whether lines that don't match need to be passed to `context`.
+    pub(crate) fn wants_context(&self) -> bool {
+        !self.config.only_matching && (self.config.before_context.is_some() || self.config.after_context.is_some())
+    }
+
+    /// Starts a new input called `name`, which prefixes its lines if `show_name` is set.
+    pub(crate) fn begin(&mut self, name: &str, show_name: bool) -> io::Result<()> {
+        self.name = name.to_string();
+        self.show_name = show_name;
+        self.before.clear();
+        self.after_remaining = 0;
+        self.last_printed = None;
+        if self.config.output == OutputMode::Json {
+            writeln!(self.out, r#"{{"type":"begin","path":{}}}"#, json_string(&self.name))?;
+        }
+        Ok(())
+    }
+
+    /// Says where the text of the current input is in the input, which it isn't byte
+    /// for byte if it had to be decoded.
+    pub(crate) fn set_raw_offsets(&mut self, raw_offsets: RawOffsets) {
+        self.raw_offsets = raw_offsets;
+    }
+
+    /// Says where the block of text that lines passed in next come from had bytes that
+    /// weren't UTF-8.
+    pub(crate) fn set_replacements(&mut self, replacements: Replacements) {
+        // Lines before the block are done with.
+        self.raw_offsets.forget_before(replacements.start);
+        self.replacements = replacements;
+    }
+
+    /// Takes an offset in the text searched to the offset it stands for in the input.
+    fn raw_offset(&self, offset: usize) -> usize {
+        self.raw_offsets.raw_offset(self.replacements.raw_offset(offset))
+    }
+
+    /// Finishes the current input, printing its summary if the output mode has one.
+    pub(crate) fn end(&mut self, matched_lines: usize) -> io::Result<()> {
+        let listed = match self.config.output {
+            OutputMode::Lines => return Ok(()),
+            OutputMode::Count => {
+                if self.show_name {
+                    self.write_name(Some(':'))?;
+                }
+                return writeln!(self.out, "{matched_lines}");
+            }
+            OutputMode::FilesWithMatches => matched_lines > 0,
+            OutputMode::FilesWithoutMatch => matched_lines == 0,
+            OutputMode::Json => {
+                let path = json_string(&self.name);
+                return writeln!(self.out, r#"{{"type":"end","path":{path},"matched_lines":{matched_lines}}}"#);
+            }
+        };
+        if listed {
+            self.write_name(None)?;
+            writeln!(self.out)?;
+        }
+        Ok(())
+    }
+
+    /// What goes between the output of inputs printed apart, being the `--` that
+    /// would have separated their groups had this printer printed them all.
+    pub(crate) fn group_separator(&self) -> Vec<u8> {
+        if self.config.output != OutputMode::Lines || !self.wants_context() {
+            Vec::new()
+        } else if self.color {
+            format!("{SEPARATOR_COLOR}--{RESET}\n").into_bytes()
+        } else {
+            b"--\n".to_vec()
+        }
+    }
+
+    pub(crate) fn matched(&mut self, matched: &Match) -> io::Result<()> {
+        let byte_offset = self.raw_offset(matched.byte_offset);
+        if self.config.only_matching && self.config.output != OutputMode::Json {
+            for span in matched.spans.iter().filter(|span| !span.is_empty()) {
+                let text = &matched.line[span.clone()];
+                let whole = 0..text.len();
+                let offset = self.raw_offset(matched.byte_offset + span.start);
+                self.write_line(matched.line_number, offset, text, slice::from_ref(&whole), ':')?;
+            }
+            return Ok(());
+        }
+
+        while let Some(line) = self.before.pop_front() {
+            self.write_line(line.line_number, line.byte_offset, &line.line, &[], '-')?;
+        }
+        if self.config.output == OutputMode::Json {
+            // Where each submatch is in the line as it is in the input.
+            let submatches: Vec<_> = matched
+                .spans
+                .iter()
+                .filter(|span| !span.is_empty())
+                .map(|span| {
+                    let start = self.raw_offset(matched.byte_offset + span.start) - byte_offset;
+                    let end = self.raw_offset(matched.byte_offset + span.end) - byte_offset;
+                    (&matched.line[span.clone()], start..end)
+                })
+                .collect();
+            self.write_json_line(matched.line_number, byte_offset, matched.line, Some(&submatches))?;
+        } else {
+            self.write_line(matched.line_number, byte_offset, matched.line, &matched.spans, ':')?;
+        }
+        self.after_remaining = self.config.after_context.unwrap_or(0);
+        Ok(())
+    }
+
+    /// Says that the current input matched, in place of lines that may not be text.
+    pub(crate) fn binary_matched(&mut self) -> io::Result<()> {
+        if self.config.output == OutputMode::Json {
+            return writeln!(self.out, r#"{{"type":"binary","path":{}}}"#, json_string(&self.name));
+        }
+        writeln!(self.out, "Binary file {} matches", self.name)
+    }
+
+    /// Writes the totals for the whole search, for `OutputMode::Json`.
+    pub(crate) fn summary(&mut self, summary: &Summary) -> io::Result<()> {
+        let Summary { matched_lines, errors, files_searched, files_matched, .. } = summary;
+        write!(self.out, r#"{{"type":"summary","files_searched":{files_searched},"files_matched":{files_matched},"#)?;
+        writeln!(self.out, r#""matched_lines":{matched_lines},"errors":{errors}}}"#)
+    }
+
+    /// Handles a line that didn't match, printing it if it is within context of a match.
+    pub(crate) fn context(&mut self, line: &Match) -> io::Result<()> {
+        let byte_offset = self.raw_offset(line.byte_offset);
+        if self.after_remaining > 0 {
+            self.after_remaining -= 1;
+            return self.write_line(line.line_number, byte_offset, line.line, &[], '-');
+        }
+        let before_context = self.config.before_context.unwrap_or(0);
+        if before_context > 0 {
+            if self.before.len() == before_context {
+                self.before.pop_front();
+            }
+            self.before.push_back(Buffered {
+                line_number: line.line_number,
+                byte_offset,
+                line: line.line.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    fn write_line(
+        &mut self,
+        line_number: usize,
+        byte_offset: usize,
+        line: &str,
+        spans: &[Range<usize>],
+        separator: char,
+    ) -> io::Result<()> {
+        if self.config.output == OutputMode::Json {
+            return self.write_json_line(line_number, byte_offset, line, None);
+        }
+        let contiguous = self.last_printed.is_some_and(|last| last + 1 == line_number);
+        if self.wants_context() && self.printed_any && !contiguous {
+            self.paint(SEPARATOR_COLOR, "--")?;
+            writeln!(self.out)?;
+        }
+        self.last_printed = Some(line_number);
+        self.printed_any = true;
+
+        if self.show_name {
+            self.write_name(Some(separator))?;
+        }
+        if self.config.line_number {
+            self.paint(NUMBER_COLOR, line_number)?;
+            self.paint(SEPARATOR_COLOR, separator)?;
+        }
+        if self.config.byte_offset {
+            self.paint(NUMBER_COLOR, byte_offset)?;
+            self.paint(SEPARATOR_COLOR, separator)?;
+        }
+
+        let mut written = 0;
+        for span in spans.iter().filter(|span| !span.is_empty()) {
+            write!(self.out, "{}", &line[written..span.start])?;
+            self.paint(MATCH_COLOR, &line[span.clone()])?;
+            written = span.end;
+        }
+        writeln!(self.out, "{}", &line[written..])
+    }
+
+    /// Writes a line as a JSON object: a `match` with the text of each submatch and
+    /// where it is in the line, in bytes as they are in the input, or `context` for
+    /// a line with no submatches.
+    fn write_json_line(
+        &mut self,
+        line_number: usize,
+        byte_offset: usize,
+        line: &str,
+        submatches: Option<&[(&str, Range<usize>)]>,
+    ) -> io::Result<()> {
+        let kind = if submatches.is_some() { "match" } else { "context" };
+        let path = json_string(&self.name);
+        write!(
+            self.out,
+            r#"{{"type":"{kind}","path":{path},"line_number":{line_number},"byte_offset":{byte_offset},"line":{}"#,
+            json_string(line)
+        )?;
+        if let Some(submatches) = submatches {
+            let submatches: Vec<String> = submatches
+                .iter()
+                .map(|(text, span)| format!(r#"{{"text":{},"start":{},"end":{}}}"#, json_string(text), span.start, span.end))
+                .collect();
+            write!(self.out, r#","submatches":[{}]"#, submatches.join(","))?;
+        }
+        writeln!(self.out, "}}")
+    }
+
+    fn write_name(&mut self, separator: Option<char>) -> io::Result<()> {
+        if self.color {
+            write!(self.out, "{NAME_COLOR}{}{RESET}", self.name)?;
+        } else {
+            write!(self.out, "{}", self.name)?;
+        }
+        match separator {
+            Some(separator) => self.paint(SEPARATOR_COLOR, separator),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes `text`, wrapped in `color` if coloring is on.
+    fn paint(&mut self, color: &str, text: impl Display) -> io::Result<()> {
+        if self.color {
+            write!(self.out, "{color}{text}{RESET}")
+        } else {
+            write!(self.out, "{text}")
+        }
+    }
+}
+
+/// Quotes `text` as a JSON string.
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c < ' ' || c == '\u{7f}' => quoted.push_str(&format!("\\u{:04x}", u32::from(c))),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lines, search, Matcher};
+
+    fn print(config: &Config, contents: &str) -> String {
+        let matcher = Matcher::new(config);
+        let mut printer = Printer::new(config, Vec::new(), false);
+        printer.begin("test", false).unwrap();
+
+        let mut matches = search(&matcher, contents).into_iter().peekable();
+        for line in lines(contents) {
+            match matches.next_if(|m| m.line_number == line.line_number) {
+                Some(matched) => printer.matched(&matched).unwrap(),
+                None => printer.context(&line).unwrap(),
+            }
+        }
+        String::from_utf8(printer.out).unwrap()
+    }
+
+    #[test]
+    fn context_groups() {
+        let contents = "1\n2 x\n3\n4\n5\n6\n7 x\n8 x\n9\n10";
+
+        let config = Config { patterns: vec!["x".to_string()], after_context: Some(1), line_number: true, ..Config::default() };
+        assert_eq!(print(&config, contents), "2:2 x\n3-3\n--\n7:7 x\n8:8 x\n9-9\n");
+
+        let config = Config { patterns: vec!["x".to_string()], before_context: Some(2), after_context: Some(2), ..Config::default() };
+        assert_eq!(print(&config, contents), "1\n2 x\n3\n4\n5\n6\n7 x\n8 x\n9\n10\n");
+
+        let config = Config { patterns: vec!["x".to_string()], before_context: Some(1), ..Config::default() };
+        assert_eq!(print(&config, contents), "1\n2 x\n--\n6\n7 x\n8 x\n");
+
+        // No lines of context still separates groups, as `-C0` does in grep.
+        let config = Config { patterns: vec!["x".to_string()], before_context: Some(0), after_context: Some(0), ..Config::default() };
+        assert_eq!(print(&config, contents), "2 x\n--\n7 x\n8 x\n");
+    }
+
+    #[test]
+    fn summaries() {
+        let config = Config { output: OutputMode::Count, ..Config::default() };
+        let mut printer = Printer::new(&config, Vec::new(), false);
+        printer.begin("a.txt", true).unwrap();
+        printer.end(3).unwrap();
+        printer.begin("b.txt", true).unwrap();
+        printer.end(0).unwrap();
+        assert_eq!(String::from_utf8(printer.out).unwrap(), "a.txt:3\nb.txt:0\n");
+
+        let config = Config { output: OutputMode::FilesWithoutMatch, ..Config::default() };
+        let mut printer = Printer::new(&config, Vec::new(), false);
+        printer.begin("a.txt", false).unwrap();
+        printer.end(3).unwrap();
+        printer.begin("b.txt", false).unwrap();
+        printer.end(0).unwrap();
+        assert_eq!(String::from_utf8(printer.out).unwrap(), "b.txt\n");
+    }
+
+    #[test]
+    fn colors() {
+        let config = Config { patterns: vec!["o".to_string()], line_number: true, ..Config::default() };
+        let mut printer = Printer::new(&config, Vec::new(), true);
+        printer.begin("poem.txt", true).unwrap();
+        printer.matched(&search(&Matcher::new(&config), "frogs or toads")[0]).unwrap();
+
+        assert_eq!(
+            String::from_utf8(printer.out).unwrap(),
+            "\x1b[35mpoem.txt\x1b[0m\x1b[36m:\x1b[0m\x1b[32m1\x1b[0m\x1b[36m:\x1b[0m\
+             fr\x1b[1;31mo\x1b[0mgs \x1b[1;31mo\x1b[0mr t\x1b[1;31mo\x1b[0mads\n"
+        );
+    }
+
+    #[test]
+    fn group_separators() {
+        let config = Config { patterns: vec!["x".to_string()], after_context: Some(1), ..Config::default() };
+        assert_eq!(Printer::new(&config, Vec::new(), false).group_separator(), b"--\n");
+        assert_eq!(Printer::new(&config, Vec::new(), true).group_separator(), b"\x1b[36m--\x1b[0m\n");
+
+        let config = Config { output: OutputMode::Count, after_context: Some(1), ..Config::default() };
+        assert_eq!(Printer::new(&config, Vec::new(), false).group_separator(), b"");
+        let config = Config { patterns: vec!["x".to_string()], ..Config::default() };
+        assert_eq!(Printer::new(&config, Vec::new(), false).group_separator(), b"");
+    }
+
+    #[test]
+    fn only_matching() {
+        let config = Config { patterns: vec!["o".to_string()], only_matching: true, byte_offset: true, ..Config::default() };
+        let mut printer = Printer::new(&config, Vec::new(), false);
+        printer.begin("poem.txt", false).unwrap();
+        for matched in search(&Matcher::new(&config), "frogs\nor toads") {
+            printer.matched(&matched).unwrap();
+        }
+
+        assert_eq!(String::from_utf8(printer.out).unwrap(), "2:o\n6:o\n10:o\n");
+    }
+
+    #[test]
+    fn json_events() {
+        let config =
+            Config { patterns: vec!["o".to_string()], output: OutputMode::Json, after_context: Some(1), ..Config::default() };
+        let path = r#""path":"test""#;
+        let line = r#""line_number":1,"byte_offset":0,"line":"say \"no\"\tto\u0001""#;
+        let submatches = r#"[{"text":"o","start":6,"end":7},{"text":"o","start":10,"end":11}]"#;
+        let expected = [
+            format!(r#"{{"type":"begin",{path}}}"#),
+            format!(r#"{{"type":"match",{path},{line},"submatches":{submatches}}}"#),
+            format!(r#"{{"type":"context",{path},"line_number":2,"byte_offset":13,"line":"fine"}}"#),
+        ];
+        assert_eq!(print(&config, "say \"no\"\tto\u{1}\nfine"), expected.join("\n") + "\n");
+
+        let mut printer = Printer::new(&config, Vec::new(), true);
+        printer.begin("a \\ \"b\".txt", false).unwrap();
+        printer.end(0).unwrap();
+        printer.summary(&Summary { matched_lines: 1, errors: 2, files_searched: 3, files_matched: 1, ..Summary::default() }).unwrap();
+        let expected = r#"{"type":"begin","path":"a \\ \"b\".txt"}
+{"type":"end","path":"a \\ \"b\".txt","matched_lines":0}
+{"type":"summary","files_searched":3,"files_matched":1,"matched_lines":1,"errors":2}
+"#;
+        assert_eq!(String::from_utf8(printer.out).unwrap(), expected);
+    }
+}
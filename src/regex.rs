@@ -0,0 +1,731 @@
+//! A small regular expression engine used by `--regex`.
+//!
+//! Patterns are parsed into a syntax tree and compiled into a program that is
+//! executed by a Pike VM, so matching time is linear in the length of the line
+//! no matter how the pattern is written. The supported syntax is:
+//!
+//! ```text
+//! ^ $         start / end of the line
+//! .           any character
+//! * + ?       zero or more, one or more, zero or one (greedy)
+//! a|b         alternation
+//! (...)       grouping, (?:...) is accepted as well
+//! [...]       character class, [^...] negated, ranges like a-z
+//! \d \w \s    digit, word and whitespace characters (\D \W \S negated)
+//! \n \t \r    newline, tab, carriage return
+//! \x          any other punctuation character x taken literally
+//! ```
+//!
+//! Ignoring case, characters given literally match the way the literal
+//! search does, with full case folding, so `straße` matches "STRASSE". A class
+//! only compares one character at a time, though: `stra[ß]e` doesn't.
+
+use std::error::Error as StdError;
+use std::fmt;
+
+use crate::casefold::{fold, simple_fold, Fold};
+
+/// A compiled regular expression, possibly made of several patterns.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Regex {
+    patterns: Vec<String>,
+    flags: Flags,
+    program: Vec<Inst>,
+}
+
+/// Options that change how a pattern matches without being part of its syntax.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Flags {
+    /// Match letters without regard to case.
+    pub ignore_case: bool,
+    /// Only match where the match is neither preceded nor followed by a word character.
+    pub whole_word: bool,
+    /// Only match the whole of the text.
+    pub whole_line: bool,
+}
+
+/// An error produced when a pattern could not be compiled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+    message: String,
+    position: usize,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "regex parse error at position {}: {}", self.position, self.message)
+    }
+}
+
+impl StdError for Error {}
+
+impl Regex {
+    /// Compiles `pattern`, optionally matching letters without regard to case.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let re = minigrep::regex::Regex::new("^fr(o|e)g+$", false).unwrap();
+    /// assert!(re.is_match("frogg"));
+    /// assert!(!re.is_match("a frog"));
+    /// assert!(minigrep::regex::Regex::new("(frog", false).is_err());
+    /// ```
+    pub fn new(pattern: &str, ignore_case: bool) -> Result<Regex, Error> {
+        Regex::with_flags(pattern, Flags { ignore_case, ..Flags::default() })
+    }
+
+    /// Compiles `pattern` with every option in `flags`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use minigrep::regex::{Flags, Regex};
+    ///
+    /// let re = Regex::with_flags("r?ust", Flags { whole_word: true, ..Flags::default() }).unwrap();
+    /// assert!(re.is_match("in rust we trust"));
+    /// assert!(!re.is_match("we trust"));
+    /// ```
+    pub fn with_flags(pattern: &str, flags: Flags) -> Result<Regex, Error> {
+        Regex::any_of(&[pattern], flags)
+    }
+
+    /// Compiles a regex that matches wherever any of `patterns` does. With no
+    /// patterns at all it never matches.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use minigrep::regex::{Flags, Regex};
+    ///
+    /// let re = Regex::any_of(&["^Then", "frog$"], Flags::default()).unwrap();
+    /// assert!(re.is_match("How public, like a frog"));
+    /// assert!(!re.is_match("To an admiring bog!"));
+    /// ```
+    pub fn any_of(patterns: &[impl AsRef<str>], flags: Flags) -> Result<Regex, Error> {
+        let mut branches = Vec::new();
+        for pattern in patterns {
+            branches.push(Parser::new(pattern.as_ref()).parse()?);
+        }
+        let mut node = match branches.len() {
+            // A class with nothing in it can never match.
+            0 => Node::Class(Class { negated: false, items: Vec::new() }),
+            1 => branches.pop().unwrap(),
+            _ => Node::Alternate(branches),
+        };
+        if flags.whole_word {
+            node = Node::Concat(vec![Node::Look(Look::NotWordBefore), node, Node::Look(Look::NotWordAfter)]);
+        }
+        if flags.whole_line {
+            node = Node::Concat(vec![Node::Look(Look::Start), node, Node::Look(Look::End)]);
+        }
+
+        let mut compiler = Compiler { program: Vec::new(), ignore_case: flags.ignore_case };
+        compiler.compile(&node);
+        compiler.program.push(Inst::Match);
+
+        Ok(Regex {
+            patterns: patterns.iter().map(|pattern| pattern.as_ref().to_string()).collect(),
+            flags,
+            program: compiler.program,
+        })
+    }
+
+    /// The patterns this regex was compiled from.
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    /// The flags this regex was compiled with.
+    pub fn flags(&self) -> Flags {
+        self.flags
+    }
+
+    /// Compiles the same patterns again with different flags.
+    pub fn recompile(&self, flags: Flags) -> Regex {
+        Regex::any_of(&self.patterns, flags).expect("patterns compiled before")
+    }
+
+    /// Returns true if the regex matches anywhere in `text`.
+    pub fn is_match(&self, text: &str) -> bool {
+        self.find_at(text, 0).is_some()
+    }
+
+    /// Finds the leftmost match in `text` that starts at or after byte `start`,
+    /// returning its byte range. Anchors still refer to the whole of `text`.
+    pub fn find_at(&self, text: &str, start: usize) -> Option<(usize, usize)> {
+        let mut current = Threads::new(self.program.len());
+        let mut next = Threads::new(self.program.len());
+        let mut matched = None;
+        let mut pos = start;
+
+        loop {
+            if matched.is_none() {
+                self.add_thread(&mut current, 0, pos, text, pos);
+            }
+            if current.list.is_empty() && matched.is_some() {
+                break;
+            }
+
+            let ch = text[pos..].chars().next();
+            let after = pos + ch.map_or(0, char::len_utf8);
+            // A character like 'ß' that folds to several can take the place of as many `Char`s.
+            let long_fold = ch.filter(|c| self.flags.ignore_case && !c.is_ascii()).map(fold);
+            let long_fold = long_fold.filter(|folded| folded.clone().nth(1).is_some());
+
+            for &(pc, begin) in &current.list {
+                let step = match (&self.program[pc], ch) {
+                    (Inst::Match, _) => {
+                        matched = Some((begin, pos));
+                        break;
+                    }
+                    (Inst::Char(expected), Some(c)) if self.char_eq(*expected, c) => Some(pc + 1),
+                    (Inst::Char(_), Some(_)) => long_fold.clone().and_then(|folded| self.step_folded(pc, folded)),
+                    (Inst::Any, Some(c)) => (c != '\n').then_some(pc + 1),
+                    (Inst::Class(class), Some(c)) => class.matches(c, self.flags.ignore_case).then_some(pc + 1),
+                    _ => None,
+                };
+                if let Some(step) = step {
+                    self.add_thread(&mut next, step, begin, text, after);
+                }
+            }
+
+            if ch.is_none() {
+                break;
+            }
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+            pos = after;
+        }
+
+        matched
+    }
+
+    /// Adds a thread at `pc`, following jumps, splits and assertions to the
+    /// instructions that consume a character, which keep the order of priority.
+    fn add_thread(&self, threads: &mut Threads, pc: usize, begin: usize, text: &str, pos: usize) {
+        threads.stack.push(pc);
+        while let Some(pc) = threads.stack.pop() {
+            if threads.seen[pc] {
+                continue;
+            }
+            threads.seen[pc] = true;
+
+            match &self.program[pc] {
+                Inst::Jump(target) => threads.stack.push(*target),
+                Inst::Split(first, second) => {
+                    // Pushed in reverse, so `first` and everything it leads to comes first.
+                    threads.stack.push(*second);
+                    threads.stack.push(*first);
+                }
+                Inst::Assert(look) => {
+                    if look.holds(text, pos) {
+                        threads.stack.push(pc + 1);
+                    }
+                }
+                _ => threads.list.push((pc, begin)),
+            }
+        }
+    }
+
+    fn char_eq(&self, expected: char, c: char) -> bool {
+        expected == c || (self.flags.ignore_case && simple_fold(expected) == simple_fold(c))
+    }
+
+    /// Where a thread at `pc` goes on taking a character that folds to `folded`, if
+    /// the `Char`s from there on spell that out.
+    fn step_folded(&self, mut pc: usize, folded: Fold) -> Option<usize> {
+        for c in folded {
+            match self.program[pc] {
+                Inst::Char(expected) if self.char_eq(expected, c) => pc += 1,
+                _ => return None,
+            }
+        }
+        Some(pc)
+    }
+}
+
+/// Whether `c` counts as part of a word for `\w` and whole-word matching.
+pub(crate) fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// The threads alive at one position of the input, in priority order.
+struct Threads {
+    list: Vec<(usize, usize)>,
+    seen: Vec<bool>,
+    /// Instructions still to be followed by `Regex::add_thread`.
+    stack: Vec<usize>,
+}
+
+impl Threads {
+    fn new(len: usize) -> Threads {
+        Threads { list: Vec::with_capacity(len), seen: vec![false; len], stack: Vec::new() }
+    }
+
+    fn clear(&mut self) {
+        self.list.clear();
+        self.seen.fill(false);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Assert(Look),
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Look {
+    Start,
+    End,
+    NotWordBefore,
+    NotWordAfter,
+}
+
+impl Look {
+    fn holds(self, text: &str, pos: usize) -> bool {
+        match self {
+            Look::Start => pos == 0,
+            Look::End => pos == text.len(),
+            Look::NotWordBefore => !text[..pos].chars().next_back().is_some_and(is_word_char),
+            Look::NotWordAfter => !text[pos..].chars().next().is_some_and(is_word_char),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Class {
+    negated: bool,
+    items: Vec<ClassItem>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ClassItem {
+    Range(char, char),
+    Perl(Perl, bool),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Perl {
+    Digit,
+    Word,
+    Space,
+}
+
+impl Perl {
+    fn matches(self, c: char) -> bool {
+        match self {
+            Perl::Digit => c.is_ascii_digit(),
+            Perl::Word => is_word_char(c),
+            Perl::Space => c.is_whitespace(),
+        }
+    }
+}
+
+impl Class {
+    fn matches(&self, c: char, ignore_case: bool) -> bool {
+        let found = self.contains(c)
+            || (ignore_case
+                && (self.contains(simple_fold(c))
+                    || c.to_uppercase().any(|u| self.contains(u))));
+        found != self.negated
+    }
+
+    fn contains(&self, c: char) -> bool {
+        self.items.iter().any(|item| match *item {
+            ClassItem::Range(lo, hi) => lo <= c && c <= hi,
+            ClassItem::Perl(perl, negated) => perl.matches(c) != negated,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    Look(Look),
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat(Box<Node>, Repeat),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Repeat {
+    ZeroOrMore,
+    OneOrMore,
+    ZeroOrOne,
+}
+
+/// How deeply groups may nest. Parsing and compiling recurse once per level, so
+/// this keeps a pattern from running the stack out.
+const MAX_NESTING: usize = 250;
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    /// How many groups the parser is inside.
+    depth: usize,
+}
+
+impl Parser {
+    fn new(pattern: &str) -> Parser {
+        Parser { chars: pattern.chars().collect(), pos: 0, depth: 0 }
+    }
+
+    fn parse(mut self) -> Result<Node, Error> {
+        let node = self.parse_alternation()?;
+        match self.peek() {
+            None => Ok(node),
+            Some(_) => Err(self.error("unopened group")),
+        }
+    }
+
+    fn error(&self, message: &str) -> Error {
+        Error { message: message.to_string(), position: self.pos }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn eat(&mut self, expected: &str) -> bool {
+        let found = expected.chars().enumerate().all(|(i, c)| self.chars.get(self.pos + i) == Some(&c));
+        if found {
+            self.pos += expected.chars().count();
+        }
+        found
+    }
+
+    fn parse_alternation(&mut self) -> Result<Node, Error> {
+        let mut branches = vec![self.parse_concat()?];
+        while self.eat("|") {
+            branches.push(self.parse_concat()?);
+        }
+        Ok(if branches.len() == 1 { branches.pop().unwrap() } else { Node::Alternate(branches) })
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, Error> {
+        let mut items = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            items.push(self.parse_repeat()?);
+        }
+        Ok(match items.len() {
+            0 => Node::Empty,
+            1 => items.pop().unwrap(),
+            _ => Node::Concat(items),
+        })
+    }
+
+    fn parse_repeat(&mut self) -> Result<Node, Error> {
+        let mut node = self.parse_atom()?;
+        loop {
+            let repeat = match self.peek() {
+                Some('*') => Repeat::ZeroOrMore,
+                Some('+') => Repeat::OneOrMore,
+                Some('?') => Repeat::ZeroOrOne,
+                _ => return Ok(node),
+            };
+            self.bump();
+            node = match node {
+                // Repeating a repetition again is the same as repeating it once, as
+                // `a++` is `a+`, or as `a*` if the two differ. This keeps repeats
+                // from nesting however many operators are stacked up.
+                Node::Repeat(inner, previous) if previous == repeat => Node::Repeat(inner, repeat),
+                Node::Repeat(inner, _) => Node::Repeat(inner, Repeat::ZeroOrMore),
+                node => Node::Repeat(Box::new(node), repeat),
+            };
+        }
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, Error> {
+        let c = match self.bump() {
+            Some(c) => c,
+            None => return Err(self.error("unexpected end of pattern")),
+        };
+
+        match c {
+            '(' => {
+                if self.depth == MAX_NESTING {
+                    self.pos -= 1;
+                    return Err(self.error("groups nested too deeply"));
+                }
+                self.eat("?:");
+                self.depth += 1;
+                let node = self.parse_alternation()?;
+                self.depth -= 1;
+                if !self.eat(")") {
+                    return Err(self.error("unclosed group"));
+                }
+                Ok(node)
+            }
+            '*' | '+' | '?' => {
+                self.pos -= 1;
+                Err(self.error("repetition operator missing expression"))
+            }
+            '[' => self.parse_class(),
+            '.' => Ok(Node::Any),
+            '^' => Ok(Node::Look(Look::Start)),
+            '$' => Ok(Node::Look(Look::End)),
+            '\\' => match self.parse_escape()? {
+                ClassItem::Range(c, _) => Ok(Node::Char(c)),
+                perl => Ok(Node::Class(Class { negated: false, items: vec![perl] })),
+            },
+            c => Ok(Node::Char(c)),
+        }
+    }
+
+    /// Parses the character after a backslash, returning it either as a
+    /// single-character range or as a Perl class like `\d`.
+    fn parse_escape(&mut self) -> Result<ClassItem, Error> {
+        let c = match self.bump() {
+            Some(c) => c,
+            None => return Err(self.error("trailing backslash")),
+        };
+
+        let item = match c {
+            'd' => ClassItem::Perl(Perl::Digit, false),
+            'D' => ClassItem::Perl(Perl::Digit, true),
+            'w' => ClassItem::Perl(Perl::Word, false),
+            'W' => ClassItem::Perl(Perl::Word, true),
+            's' => ClassItem::Perl(Perl::Space, false),
+            'S' => ClassItem::Perl(Perl::Space, true),
+            'n' => ClassItem::Range('\n', '\n'),
+            't' => ClassItem::Range('\t', '\t'),
+            'r' => ClassItem::Range('\r', '\r'),
+            c if c.is_alphanumeric() => {
+                self.pos -= 1;
+                return Err(self.error(&format!("unrecognized escape sequence \\{c}")));
+            }
+            c => ClassItem::Range(c, c),
+        };
+        Ok(item)
+    }
+
+    fn parse_class(&mut self) -> Result<Node, Error> {
+        let negated = self.eat("^");
+        let mut items = Vec::new();
+        let mut first = true;
+
+        loop {
+            let c = match self.bump() {
+                Some(c) => c,
+                None => return Err(self.error("unclosed character class")),
+            };
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+
+            let item = if c == '\\' { self.parse_escape()? } else { ClassItem::Range(c, c) };
+            let lo = match item {
+                ClassItem::Range(lo, _) => lo,
+                perl => {
+                    items.push(perl);
+                    continue;
+                }
+            };
+
+            if self.peek() == Some('-') && !matches!(self.chars.get(self.pos + 1), None | Some(']')) {
+                self.bump();
+                let hi = match self.bump() {
+                    Some('\\') => match self.parse_escape()? {
+                        ClassItem::Range(hi, _) => hi,
+                        _ => return Err(self.error("invalid class range")),
+                    },
+                    Some(hi) => hi,
+                    None => return Err(self.error("unclosed character class")),
+                };
+                if hi < lo {
+                    return Err(self.error("invalid class range"));
+                }
+                items.push(ClassItem::Range(lo, hi));
+            } else {
+                items.push(item);
+            }
+        }
+
+        Ok(Node::Class(Class { negated, items }))
+    }
+}
+
+struct Compiler {
+    program: Vec<Inst>,
+    ignore_case: bool,
+}
+
+impl Compiler {
+    fn compile(&mut self, node: &Node) {
+        match node {
+            Node::Empty => {}
+            // Folded in full, so 'ß' becomes "ss" and matches "SS" as well as 'ẞ'.
+            Node::Char(c) if self.ignore_case => self.program.extend(fold(*c).map(Inst::Char)),
+            Node::Char(c) => self.program.push(Inst::Char(*c)),
+            Node::Any => self.program.push(Inst::Any),
+            Node::Class(class) => self.program.push(Inst::Class(class.clone())),
+            Node::Look(look) => self.program.push(Inst::Assert(*look)),
+            Node::Concat(items) => items.iter().for_each(|item| self.compile(item)),
+            Node::Alternate(branches) => {
+                let mut jumps = Vec::new();
+                for (i, branch) in branches.iter().enumerate() {
+                    if i + 1 < branches.len() {
+                        let split = self.emit_hole();
+                        self.compile(branch);
+                        jumps.push(self.emit_hole());
+                        let next = self.program.len();
+                        self.program[split] = Inst::Split(split + 1, next);
+                    } else {
+                        self.compile(branch);
+                    }
+                }
+                let end = self.program.len();
+                for jump in jumps {
+                    self.program[jump] = Inst::Jump(end);
+                }
+            }
+            Node::Repeat(inner, Repeat::ZeroOrMore) => {
+                let split = self.emit_hole();
+                self.compile(inner);
+                self.program.push(Inst::Jump(split));
+                let end = self.program.len();
+                self.program[split] = Inst::Split(split + 1, end);
+            }
+            Node::Repeat(inner, Repeat::OneOrMore) => {
+                let start = self.program.len();
+                self.compile(inner);
+                let end = self.program.len() + 1;
+                self.program.push(Inst::Split(start, end));
+            }
+            Node::Repeat(inner, Repeat::ZeroOrOne) => {
+                let split = self.emit_hole();
+                self.compile(inner);
+                let end = self.program.len();
+                self.program[split] = Inst::Split(split + 1, end);
+            }
+        }
+    }
+
+    /// Reserves a slot for a jump whose target is not known yet.
+    fn emit_hole(&mut self) -> usize {
+        self.program.push(Inst::Match);
+        self.program.len() - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(pattern: &str, text: &str) -> Option<(usize, usize)> {
+        Regex::new(pattern, false).unwrap().find_at(text, 0)
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(find("^Rust", "Rust: trust"), Some((0, 4)));
+        assert_eq!(find("^rust", "Rust: trust"), None);
+        assert_eq!(find("ust$", "Rust: trust"), Some((8, 11)));
+        assert_eq!(find("t.u", "Rust: trust"), Some((6, 9)));
+        assert_eq!(find("ab*c", "xacx"), Some((1, 3)));
+        assert_eq!(find("ab+c", "xacx abbbc"), Some((5, 10)));
+        assert_eq!(find("colou?r", "color"), Some((0, 5)));
+        assert_eq!(find("cat|dog", "hotdog"), Some((3, 6)));
+        assert_eq!(find("(ab)+$", "xabab"), Some((1, 5)));
+        assert_eq!(find("[0-9]+", "abc 2024!"), Some((4, 8)));
+        assert_eq!(find("[^a-z ]", "abc D"), Some((4, 5)));
+        assert_eq!(find(r"\d\s\w+", "no 1 way"), Some((3, 8)));
+        assert_eq!(find(r"a\.b", "axb a.b"), Some((4, 7)));
+    }
+
+    #[test]
+    fn leftmost_first() {
+        assert_eq!(find("a|ab", "ab"), Some((0, 1)));
+        assert_eq!(find("ab|a", "ab"), Some((0, 2)));
+        assert_eq!(find("a*", "baa"), Some((0, 0)));
+        assert_eq!(Regex::new("a+", false).unwrap().find_at("abaa", 1), Some((2, 4)));
+    }
+
+    #[test]
+    fn ignore_case() {
+        let re = Regex::new("ru[s-t]+", true).unwrap();
+        assert_eq!(re.find_at("TRUST me", 0), Some((1, 5)));
+
+        // Characters that fold to more than one, on either side.
+        let re = Regex::new("stra(ß|x)e", true).unwrap();
+        assert_eq!(re.find_at("die STRASSE", 0), Some((4, 11)));
+        assert_eq!(re.find_at("die Straße", 0), Some((4, 11)));
+        assert_eq!(re.find_at("die STRAẞE", 0), Some((4, 12)));
+        let re = Regex::new("^(ss)+$", true).unwrap();
+        assert!(re.is_match("ßSsẞ"));
+        assert!(!re.is_match("ßs"));
+        assert_eq!(Regex::new("fi.e", true).unwrap().find_at("ﬁne", 0), Some((0, 5)));
+        assert_eq!(Regex::new("ﬁ.e", true).unwrap().find_at("FINE", 0), Some((0, 4)));
+        // Classes only ever compare one character at a time.
+        assert!(!Regex::new("stra[ß]e", true).unwrap().is_match("STRASSE"));
+        assert!(Regex::new("stra[ß]e", true).unwrap().is_match("STRAẞE"));
+    }
+
+    #[test]
+    fn whole_word_and_line() {
+        let word = Flags { whole_word: true, ..Flags::default() };
+        assert_eq!(Regex::with_flags("a|ab", word).unwrap().find_at("ab a", 0), Some((0, 2)));
+        assert_eq!(Regex::with_flags("t?rust", word).unwrap().find_at("trusty rust", 0), Some((7, 11)));
+        assert_eq!(Regex::with_flags("-x", word).unwrap().find_at("a-x a -x", 0), Some((6, 8)));
+
+        let line = Flags { whole_line: true, ..Flags::default() };
+        assert_eq!(Regex::with_flags("a|ab", line).unwrap().find_at("ab", 0), Some((0, 2)));
+        assert!(!Regex::with_flags("a|ab", line).unwrap().is_match("abc"));
+    }
+
+    #[test]
+    fn any_of() {
+        let re = Regex::any_of(&["a|b$", "^c"], Flags::default()).unwrap();
+        assert_eq!(re.find_at("xxb", 0), Some((2, 3)));
+        assert_eq!(re.find_at("xcxa", 0), Some((3, 4)));
+        assert!(!re.is_match("xc"));
+        assert!(!Regex::any_of(&[] as &[&str], Flags::default()).unwrap().is_match("anything"));
+    }
+
+    #[test]
+    fn compile_errors() {
+        assert_eq!(Regex::new("(ab", false).unwrap_err().to_string(), "regex parse error at position 3: unclosed group");
+        assert!(Regex::new("ab)", false).is_err());
+        assert!(Regex::new("*a", false).is_err());
+        assert!(Regex::new("[a-", false).is_err());
+        assert!(Regex::new("[z-a]", false).is_err());
+        assert!(Regex::new(r"a\", false).is_err());
+        assert!(Regex::new(r"\q", false).is_err());
+    }
+
+    #[test]
+    fn deep_and_wide_patterns() {
+        let nested = |depth| format!("{}a{}", "(".repeat(depth), ")+".repeat(depth));
+        assert!(Regex::new(&nested(MAX_NESTING), false).unwrap().is_match("aa"));
+        let err = Regex::new(&nested(20_000), false).unwrap_err();
+        assert_eq!(err.to_string(), "regex parse error at position 250: groups nested too deeply");
+
+        assert_eq!(find("ba**+?", "xbaa"), Some((1, 4)));
+        assert_eq!(find("ba++", "xbaa"), Some((1, 4)));
+        assert_eq!(find("ba??c", "xbc"), Some((1, 3)));
+        assert_eq!(find(&format!("b{}", "+".repeat(100_000)), "abb"), Some((1, 3)));
+
+        let words: Vec<String> = (0..50_000).map(|i| format!("w{i}x")).collect();
+        let re = Regex::any_of(&words, Flags::default()).unwrap();
+        assert_eq!(re.find_at("a w49999x", 0), Some((2, 9)));
+    }
+}
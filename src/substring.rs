@@ -0,0 +1,245 @@
+//! Finding a literal needle in a large haystack without looking at every byte.
+//!
+//! Most needles are found by scanning, eight bytes at a time, for the byte of
+//! the needle least likely to show up in text and checking the rest wherever
+//! it does. If that byte turns out to be common in this haystack, the search
+//! switches to Boyer-Moore-Horspool, which compares the last byte of each
+//! window first and on a mismatch jumps ahead by up to the needle's length.
+
+/// A needle prepared for searching.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finder {
+    needle: Vec<u8>,
+    /// Index in the needle of the byte `memchr` looks for.
+    rare: usize,
+    /// How far the window can move when its last byte is the index.
+    skip: Box<[usize; 256]>,
+}
+
+impl Finder {
+    pub fn new(needle: &[u8]) -> Finder {
+        let mut skip = Box::new([needle.len().max(1); 256]);
+        if let Some((_, init)) = needle.split_last() {
+            for (i, &b) in init.iter().enumerate() {
+                skip[b as usize] = needle.len() - 1 - i;
+            }
+        }
+        let rare = (0..needle.len()).min_by_key(|&i| frequency(needle[i])).unwrap_or(0);
+        Finder { needle: needle.to_vec(), rare, skip }
+    }
+
+    pub fn needle(&self) -> &[u8] {
+        &self.needle
+    }
+
+    /// Returns the index of the first occurrence of the needle in `haystack`.
+    pub fn find(&self, haystack: &[u8]) -> Option<usize> {
+        match self.needle.len() {
+            0 => Some(0),
+            1 => memchr(self.needle[0], haystack),
+            _ => self.find_rare(haystack).unwrap_or_else(|pos| self.horspool(haystack, pos)),
+        }
+    }
+
+    /// Searches from candidate to candidate, or gives up with where it got to if
+    /// the candidates come too thick and fast to be worth it.
+    fn find_rare(&self, haystack: &[u8]) -> Result<Option<usize>, usize> {
+        let byte = self.needle[self.rare];
+        let mut pos = 0;
+        let mut misses = 0;
+        while pos + self.needle.len() <= haystack.len() {
+            let start = match memchr(byte, &haystack[pos + self.rare..]) {
+                Some(i) => pos + i,
+                None => return Ok(None),
+            };
+            if start + self.needle.len() > haystack.len() {
+                return Ok(None);
+            }
+            if haystack[start..start + self.needle.len()] == *self.needle {
+                return Ok(Some(start));
+            }
+            pos = start + 1;
+            misses += 1;
+            if misses > 16 && pos < misses * 32 {
+                return Err(pos);
+            }
+        }
+        Ok(None)
+    }
+
+    fn horspool(&self, haystack: &[u8], mut pos: usize) -> Option<usize> {
+        let (&last, init) = self.needle.split_last()?;
+        while pos + self.needle.len() <= haystack.len() {
+            let end = haystack[pos + init.len()];
+            if end == last && haystack[pos..pos + init.len()] == *init {
+                return Some(pos);
+            }
+            pos += self.skip[end as usize];
+        }
+        None
+    }
+}
+
+const ONES: u64 = u64::from_ne_bytes([0x01; 8]);
+const HIGHS: u64 = u64::from_ne_bytes([0x80; 8]);
+
+/// Returns the index of the first `byte` in `haystack`.
+pub fn memchr(byte: u8, haystack: &[u8]) -> Option<usize> {
+    let repeated = ONES * byte as u64;
+    scan(haystack, |word| has_zero(word ^ repeated), |b| b == byte)
+}
+
+/// Returns the index of the first byte in `haystack` that is `a` or `b`, or isn't ASCII.
+pub fn memchr2_or_non_ascii(a: u8, b: u8, haystack: &[u8]) -> Option<usize> {
+    let (repeated_a, repeated_b) = (ONES * a as u64, ONES * b as u64);
+    scan(
+        haystack,
+        |word| word & HIGHS != 0 || has_zero(word ^ repeated_a) || has_zero(word ^ repeated_b),
+        |byte| byte == a || byte == b || !byte.is_ascii(),
+    )
+}
+
+/// Returns the index of the first byte in `haystack` that `accept` takes out of
+/// those that are one of `repeated`'s, each repeated across a word, or with
+/// `non_ascii` that aren't ASCII. Every byte of a word is found at once, so a set
+/// that shows up often still costs little more than a single byte would.
+pub(crate) fn find_set(
+    repeated: &[u64],
+    non_ascii: bool,
+    haystack: &[u8],
+    mut accept: impl FnMut(usize) -> bool,
+) -> Option<usize> {
+    let mut chunks = haystack.chunks_exact(8);
+    for (i, chunk) in chunks.by_ref().enumerate() {
+        let word = u64::from_le_bytes(chunk.try_into().unwrap());
+        let mut found = if non_ascii { word & HIGHS } else { 0 };
+        for &r in repeated {
+            found |= exact_zeros(word ^ r);
+        }
+        // One high bit for each byte found, the first in the lowest.
+        while found != 0 {
+            let at = i * 8 + found.trailing_zeros() as usize / 8;
+            if accept(at) {
+                return Some(at);
+            }
+            found &= found - 1;
+        }
+    }
+    let rest = chunks.remainder();
+    let offset = haystack.len() - rest.len();
+    rest.iter()
+        .enumerate()
+        .filter(|&(_, &byte)| (non_ascii && !byte.is_ascii()) || repeated.iter().any(|&r| r as u8 == byte))
+        .map(|(j, _)| offset + j)
+        .find(|&at| accept(at))
+}
+
+/// Goes through `haystack` sixteen bytes at a time, only looking for a byte that
+/// `wanted` accepts in words that `maybe` says could hold one.
+fn scan(haystack: &[u8], maybe: impl Fn(u64) -> bool, wanted: impl Fn(u8) -> bool) -> Option<usize> {
+    let mut chunks = haystack.chunks_exact(16);
+    for (i, chunk) in chunks.by_ref().enumerate() {
+        let (a, b) = chunk.split_at(8);
+        if maybe(u64::from_ne_bytes(a.try_into().unwrap())) || maybe(u64::from_ne_bytes(b.try_into().unwrap())) {
+            if let Some(j) = chunk.iter().position(|&b| wanted(b)) {
+                return Some(i * 16 + j);
+            }
+        }
+    }
+    let rest = chunks.remainder();
+    rest.iter().position(|&b| wanted(b)).map(|j| haystack.len() - rest.len() + j)
+}
+
+/// Whether any byte of `word` is zero.
+fn has_zero(word: u64) -> bool {
+    word.wrapping_sub(ONES) & !word & HIGHS != 0
+}
+
+/// The high bit of each zero byte of `word`, and of no other.
+fn exact_zeros(word: u64) -> u64 {
+    const LOWS: u64 = u64::from_ne_bytes([0x7f; 8]);
+    !(((word & LOWS) + LOWS) | word | LOWS)
+}
+
+/// Counts the occurrences of `byte` in `haystack`.
+pub fn count(byte: u8, haystack: &[u8]) -> usize {
+    let repeated = u64::from_ne_bytes([byte; 8]);
+
+    let mut chunks = haystack.chunks_exact(8);
+    let mut count = 0;
+    for chunk in chunks.by_ref() {
+        let word = u64::from_ne_bytes(chunk.try_into().unwrap()) ^ repeated;
+        count += exact_zeros(word).count_ones() as usize;
+    }
+    count + chunks.remainder().iter().filter(|&&b| b == byte).count()
+}
+
+/// Roughly how common `byte` is in English text, from 0 for never upwards.
+pub(crate) fn frequency(byte: u8) -> usize {
+    const LETTERS: &[u8] = b"zqxjkvbpygfwmucldrhsnioate";
+    match byte {
+        b' ' => 40,
+        b'a'..=b'z' => 10 + LETTERS.iter().position(|&b| b == byte).unwrap_or(0),
+        b'A'..=b'Z' => 4 + LETTERS.iter().position(|&b| b == byte.to_ascii_lowercase()).unwrap_or(0) / 5,
+        b'0'..=b'9' | b',' | b'.' => 8,
+        0x80.. => 2,
+        _ => 4,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_first_occurrence() {
+        let haystack = b"How dreary to be somebody! How public, like a frog";
+        for needle in ["How", "frog", "g", "body!", "o", "", "How dreary to be somebody! How public, like a frog"] {
+            let expected = std::str::from_utf8(haystack).unwrap().find(needle);
+            assert_eq!(Finder::new(needle.as_bytes()).find(haystack), expected, "{needle:?}");
+        }
+        assert_eq!(Finder::new(b"toad").find(haystack), None);
+        assert_eq!(Finder::new(b"aab").find(b"aaaaaab"), Some(4));
+        assert_eq!(Finder::new(b"frogs").find(b"frog"), None);
+
+        // Every byte is a candidate here, so this ends up in `horspool`.
+        let mut haystack = vec![b'b'; 1000];
+        haystack.extend_from_slice(b"bab");
+        assert_eq!(Finder::new(b"bba").find(&haystack), Some(999));
+    }
+
+    #[test]
+    fn memchr_and_count() {
+        let haystack: Vec<u8> = (0..40).collect();
+        for byte in 0..40 {
+            assert_eq!(memchr(byte, &haystack), Some(byte as usize));
+        }
+        assert_eq!(memchr(200, &haystack), None);
+        assert_eq!(memchr(0x80, &[0x7f, 0x81, 0x00, 0xff, 0x01, 0x80, 0x80, 0x80, 0x80]), Some(5));
+        assert_eq!(memchr2_or_non_ascii(b'z', b'Z', b"Sherlock Holmes went to the Zoo"), Some(28));
+        assert_eq!(memchr2_or_non_ascii(b'z', b'Z', "Sherlock Holmes went to the Zoo in Zürich".as_bytes()), Some(28));
+        assert_eq!(memchr2_or_non_ascii(b'z', b'Z', "Sherlock Holmes went to the Ürich".as_bytes()), Some(28));
+        assert_eq!(memchr2_or_non_ascii(b'z', b'Z', b"Sherlock Holmes"), None);
+
+        let haystack = b"\n\n\x0b\x8a\nfrog\n\n\n\x09\x0a\x0a\x0a\x0a";
+        assert_eq!(count(b'\n', haystack), 10);
+        assert_eq!(count(b'\n', &haystack[1..]), 9);
+    }
+
+    #[test]
+    fn finds_a_set_of_bytes() {
+        let repeated = [u64::from_ne_bytes([b'o'; 8]), u64::from_ne_bytes([b'g'; 8])];
+        let haystack = "a bog in Zürich, full of frogs".as_bytes();
+        assert_eq!(find_set(&repeated, false, haystack, |_| true), Some(3));
+        assert_eq!(find_set(&repeated, true, haystack, |i| i > 5), Some(10));
+        // Taking only the last of them means looking at every one, in order.
+        let mut seen = Vec::new();
+        let last = find_set(&repeated, false, haystack, |i| {
+            seen.push(i);
+            i == haystack.len() - 2
+        });
+        assert_eq!(last, Some(29));
+        assert_eq!(seen, [3, 4, 23, 28, 29]);
+        assert_eq!(find_set(&repeated, false, b"a newt", |_| true), None);
+    }
+}
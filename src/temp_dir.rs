@@ -0,0 +1,36 @@
+//! Directories of files for tests to search and walk.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::{env, process};
+
+/// A directory made under the system's temporary one for a single test, and
+/// removed again when dropped, whether the test passed or not.
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Makes a directory for the test called `name` holding `files`, each a path
+    /// relative to it along with its contents. Directories they are in are made too.
+    pub(crate) fn new(name: &str, files: &[(&str, &str)]) -> TempDir {
+        let root = env::temp_dir().join(format!("minigrep-{name}-{}", process::id()));
+        // Left over from a run that was killed before it could clean up.
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        for (path, contents) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        TempDir(root)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
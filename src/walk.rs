@@ -0,0 +1,165 @@
+//! Recursive directory traversal for `-r`.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::glob::Glob;
+use crate::ignore::Ignores;
+
+/// Yields every regular file below a root path, depth first and in file name
+/// order so that output is stable between runs.
+///
+/// Symbolic links found while walking are skipped, but a root that is itself a
+/// symbolic link is followed. Directories and entries that cannot be read are
+/// reported as an `Err` and the walk carries on with the next entry, so one bad
+/// entry doesn't lose the rest of its directory.
+///
+/// Unless turned off with `ignore_files(false)`, `.git` directories and whatever
+/// the ignore files in and above the walked directories exclude are skipped too.
+pub struct Walk {
+    /// Paths still to visit, with their depth and the ignore files of their directory.
+    stack: Vec<(PathBuf, usize, Arc<Ignores>)>,
+    /// Errors met while reading a directory, to be yielded before its entries.
+    errors: Vec<io::Error>,
+    ignore_files: bool,
+    exclude_dirs: Vec<Glob>,
+}
+
+impl Walk {
+    pub fn new(root: impl Into<PathBuf>) -> Walk {
+        Walk { stack: vec![(root.into(), 0, Arc::default())], errors: Vec::new(), ignore_files: true, exclude_dirs: Vec::new() }
+    }
+
+    /// Sets whether `.gitignore`, `.ignore` and `.minigrepignore` files are honored.
+    pub fn ignore_files(self, yes: bool) -> Walk {
+        Walk { ignore_files: yes, ..self }
+    }
+
+    /// Skips directories below the root that any of `globs` matches.
+    pub fn exclude_dirs(self, globs: Vec<Glob>) -> Walk {
+        Walk { exclude_dirs: globs, ..self }
+    }
+}
+
+impl Iterator for Walk {
+    type Item = io::Result<PathBuf>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if !self.errors.is_empty() {
+                return Some(Err(self.errors.remove(0)));
+            }
+            let (path, depth, ignores) = self.stack.pop()?;
+            let metadata = if depth == 0 { fs::metadata(&path) } else { fs::symlink_metadata(&path) };
+            let metadata = match metadata {
+                Ok(metadata) => metadata,
+                Err(err) => return Some(Err(with_path(err, &path))),
+            };
+
+            if metadata.is_file() {
+                return Some(Ok(path));
+            }
+            if !metadata.is_dir() {
+                continue;
+            }
+
+            let entries = match fs::read_dir(&path) {
+                Ok(entries) => entries,
+                Err(err) => return Some(Err(with_path(err, &path))),
+            };
+            let ignores = match (self.ignore_files, depth) {
+                (false, _) => ignores,
+                (true, 0) => Ignores::above(&path).enter(&path),
+                (true, _) => ignores.enter(&path),
+            };
+            let mut children = Vec::new();
+            for entry in entries {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(err) => {
+                        self.errors.push(with_path(err, &path));
+                        continue;
+                    }
+                };
+                let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+                let child = entry.path();
+                if self.ignore_files && (entry.file_name() == ".git" || ignores.is_ignored(&child, is_dir)) {
+                    continue;
+                }
+                if is_dir && self.exclude_dirs.iter().any(|glob| glob.is_match_path(&child)) {
+                    continue;
+                }
+                children.push(child);
+            }
+            children.sort();
+            self.stack.extend(children.into_iter().rev().map(|child| (child, depth + 1, Arc::clone(&ignores))));
+        }
+    }
+}
+
+/// Prefixes an I/O error's message with the path it happened on.
+pub(crate) fn with_path(err: io::Error, path: &Path) -> io::Error {
+    io::Error::new(err.kind(), format!("{}: {err}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    #[test]
+    fn walks_in_order() {
+        let files = [("b/inner/z.txt", "z"), ("b/c.txt", "c"), ("a/d.txt", "d"), ("top.txt", "top")];
+        let dir = TempDir::new("walk", &files);
+        let root = dir.path();
+
+        let found: Vec<PathBuf> = Walk::new(root)
+            .exclude_dirs(vec![Glob::new("in*")])
+            .map(|path| path.unwrap().strip_prefix(root).unwrap().to_path_buf())
+            .collect();
+
+        let expected: Vec<PathBuf> = ["a/d.txt", "b/c.txt", "top.txt"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn skips_ignored() {
+        let dir = TempDir::new(
+            "walk-ignore",
+            &[
+                (".gitignore", "target/\n*.bak\n"),
+                (".minigrepignore", "!keep.bak\n"),
+                (".git/config", ""),
+                ("target/debug/out.txt", ""),
+                ("src/main.rs", ""),
+                ("src/main.bak", ""),
+                ("src/keep.bak", ""),
+            ],
+        );
+        let root = dir.path();
+
+        let walk = |from: &Path, ignore_files| -> Vec<PathBuf> {
+            Walk::new(from)
+                .ignore_files(ignore_files)
+                .map(|path| path.unwrap().strip_prefix(root).unwrap().to_path_buf())
+                .collect()
+        };
+        let honored = walk(root, true);
+        let all = walk(root, false);
+        // The ignore files at the top of the repository still count from inside it.
+        let src = walk(&root.join("src"), true);
+
+        let expected: Vec<PathBuf> = [".gitignore", ".minigrepignore", "src/keep.bak", "src/main.rs"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        assert_eq!(honored, expected);
+        assert_eq!(all.len(), 7);
+        assert_eq!(src, expected[2..]);
+    }
+}